@@ -0,0 +1,435 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use parking_lot::Mutex;
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::messages;
+use crate::types::BackupMode;
+
+/// 备份目录中记录 当前路径 => 原图片路径 的索引文件
+const INDEX_FILE_NAME: &str = "backup_index.json";
+
+/// 原地去水印时原图片的备份，备份位于漫画目录旁边的`漫画名.原图备份`目录或`漫画名.原图备份.zip`压缩包
+///
+/// 压缩包模式下，处理过程中原图片依然先移动到备份目录，`finish`时才打包成压缩包，
+/// 打开已有的压缩包时会先把它解压到备份目录
+pub struct Backup {
+    manga_dir: PathBuf,
+    backup_dir: PathBuf,
+    archive_path: PathBuf,
+    mode: BackupMode,
+    // 漫画目录中的相对路径 => 备份目录中原图片的相对路径
+    // 去水印后图片的后缀可能会变(比如webp变成jpg)，所以两者不一定相同
+    index: Mutex<BTreeMap<PathBuf, PathBuf>>,
+}
+
+impl Backup {
+    /// 打开漫画目录`manga_dir`的备份，备份不存在时会在第一次备份原图片时创建
+    pub fn open(manga_dir: &Path, mode: BackupMode) -> anyhow::Result<Self> {
+        let (backup_dir, archive_path) = backup_paths(manga_dir)?;
+        if archive_path.exists() {
+            extract_archive(&archive_path, &backup_dir)?;
+        }
+        let index = std::fs::read_to_string(backup_dir.join(INDEX_FILE_NAME))
+            .ok()
+            .and_then(|index_string| serde_json::from_str(&index_string).ok())
+            .unwrap_or_default();
+        Ok(Self {
+            manga_dir: manga_dir.to_path_buf(),
+            backup_dir,
+            archive_path,
+            mode,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// 只读地打开漫画目录`manga_dir`已有的备份，不会解压压缩包，也不会修改任何文件，没有备份时返回`None`
+    ///
+    /// 只能用于`original_path`查询原图片的路径，不能调用`finish`等会修改备份的方法；
+    /// 压缩包模式下原图片还在压缩包中，返回的路径可能不存在
+    pub fn open_read_only(manga_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let Some(mode) = Self::existing_mode(manga_dir)? else {
+            return Ok(None);
+        };
+        let (backup_dir, archive_path) = backup_paths(manga_dir)?;
+        // 处理过程中备份目录里的索引比压缩包里的新
+        let index_string = match std::fs::read_to_string(backup_dir.join(INDEX_FILE_NAME)) {
+            Ok(index_string) => Some(index_string),
+            Err(_) if archive_path.exists() => read_archive_index(&archive_path)?,
+            Err(_) => None,
+        };
+        let index = index_string
+            .and_then(|index_string| serde_json::from_str(&index_string).ok())
+            .unwrap_or_default();
+        Ok(Some(Self {
+            manga_dir: manga_dir.to_path_buf(),
+            backup_dir,
+            archive_path,
+            mode,
+            index: Mutex::new(index),
+        }))
+    }
+
+    /// 返回漫画目录`manga_dir`已有备份的模式，没有备份时返回`None`
+    pub fn existing_mode(manga_dir: &Path) -> anyhow::Result<Option<BackupMode>> {
+        let (backup_dir, archive_path) = backup_paths(manga_dir)?;
+        let mode = if archive_path.exists() {
+            Some(BackupMode::Archive)
+        } else if backup_dir.exists() {
+            Some(BackupMode::Folder)
+        } else {
+            None
+        };
+        Ok(mode)
+    }
+
+    /// 返回图片`img_path`的原图片在备份中的路径，不会移动任何文件，还没有备份时返回的路径不存在
+    pub fn original_path(&self, img_path: &Path) -> anyhow::Result<PathBuf> {
+        let relative_path = self.relative_path(img_path)?;
+        let original = match self.index.lock().get(relative_path) {
+            Some(original) => self.backup_dir.join(original),
+            None => self.backup_dir.join(relative_path),
+        };
+        Ok(original)
+    }
+
+    /// 在覆盖图片`img_path`之前备份它的原图片，返回备份中原图片的路径，去水印时应该读取这个路径
+    ///
+    /// 图片已经备份过(之前原地处理过)时不会再次备份，而是直接返回之前备份的原图片，
+    /// 这样重复原地处理也总是基于最初的原图片
+    pub fn backup_original(&self, img_path: &Path) -> anyhow::Result<PathBuf> {
+        let relative_path = self.relative_path(img_path)?;
+        let mut index = self.index.lock();
+        if let Some(original) = index.get(relative_path) {
+            return Ok(self.backup_dir.join(original));
+        }
+        let backup_path = self.backup_dir.join(relative_path);
+        // 索引丢失但备份还在时，以备份为准，不能用已处理过的图片覆盖原图片
+        if !backup_path.exists() {
+            move_file(img_path, &backup_path)?;
+        }
+        index.insert(relative_path.to_path_buf(), relative_path.to_path_buf());
+        Ok(backup_path)
+    }
+
+    /// 记录图片`img_path`去水印后实际写入的路径`out_image_path`，恢复时会删除它
+    pub fn record_output(&self, img_path: &Path, out_image_path: &Path) -> anyhow::Result<()> {
+        let relative_path = self.relative_path(img_path)?;
+        let out_relative_path = self.relative_path(out_image_path)?;
+        if relative_path == out_relative_path {
+            return Ok(());
+        }
+        let mut index = self.index.lock();
+        let original = index
+            .remove(relative_path)
+            .ok_or(anyhow!(messages::no_backup_for_image(img_path)))?;
+        index.insert(out_relative_path.to_path_buf(), original);
+        Ok(())
+    }
+
+    /// 将`scope_dir`(漫画目录或其中的章节目录)下的图片恢复为原图片，返回恢复的原图片路径
+    pub fn restore(&self, scope_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let scope = self.relative_path(scope_dir)?;
+        let mut index = self.index.lock();
+        let in_scope: Vec<(PathBuf, PathBuf)> = index
+            .iter()
+            .filter(|(relative_path, _)| relative_path.starts_with(scope))
+            .map(|(relative_path, original)| (relative_path.clone(), original.clone()))
+            .collect();
+        if in_scope.is_empty() {
+            return Err(anyhow!(messages::nothing_to_restore(scope_dir)));
+        }
+
+        let mut restored_paths = vec![];
+        let result = in_scope
+            .into_iter()
+            .try_for_each(|(relative_path, original)| {
+                let backup_path = self.backup_dir.join(&original);
+                // 备份中已经没有原图片(上次恢复到一半就中断了)时，当前图片可能就是恢复后的原图片，不能删除
+                if backup_path.exists() {
+                    // 删除去水印后的图片，再把原图片移回原位置
+                    let current_path = self.manga_dir.join(&relative_path);
+                    if current_path.exists() {
+                        std::fs::remove_file(&current_path)
+                            .context(messages::delete_failed(&current_path))?;
+                    }
+                    let restored_path = self.manga_dir.join(&original);
+                    move_file(&backup_path, &restored_path)?;
+                    restored_paths.push(restored_path);
+                }
+                index.remove(&relative_path);
+                anyhow::Ok(())
+            });
+        // 中途出错也要保存索引，否则已恢复的图片还留在索引中，再次恢复时会被当作去水印后的图片删除
+        self.write_index(&index)?;
+        result?;
+        Ok(restored_paths)
+    }
+
+    /// 将索引保存到备份目录，处理过程中应该定期保存，避免中途出错时丢失原图片与当前图片的对应关系
+    pub fn save_index(&self) -> anyhow::Result<()> {
+        self.write_index(&self.index.lock())
+    }
+
+    /// 先写入临时文件再重命名，避免写入中途出错时索引文件不完整
+    fn write_index(&self, index: &BTreeMap<PathBuf, PathBuf>) -> anyhow::Result<()> {
+        // 备份目录已存在时即使索引为空也要写入，否则会留下过时的索引
+        if index.is_empty() && !self.backup_dir.exists() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.backup_dir)
+            .context(messages::create_dir_failed(&self.backup_dir))?;
+        let index_path = self.backup_dir.join(INDEX_FILE_NAME);
+        let tmp_path = index_path.with_extension("json.tmp");
+        let index_string = serde_json::to_string_pretty(index)?;
+        std::fs::write(&tmp_path, index_string)
+            .context(messages::save_backup_index_failed(&index_path))?;
+        std::fs::rename(&tmp_path, &index_path)
+            .context(messages::rename_failed(&tmp_path, &index_path))?;
+        Ok(())
+    }
+
+    /// 保存索引，压缩包模式下把备份目录打包成压缩包，没有任何备份时删除备份
+    pub fn finish(&self) -> anyhow::Result<()> {
+        // 索引丢失时备份目录中可能还有原图片，这时不能删除备份
+        if self.index.lock().is_empty() && !self.has_original_files() {
+            if self.backup_dir.exists() {
+                std::fs::remove_dir_all(&self.backup_dir)
+                    .context(messages::delete_backup_dir_failed(&self.backup_dir))?;
+            }
+            if self.archive_path.exists() {
+                std::fs::remove_file(&self.archive_path)
+                    .context(messages::delete_backup_archive_failed(&self.archive_path))?;
+            }
+            return Ok(());
+        }
+
+        self.save_index()?;
+        if self.mode == BackupMode::Archive {
+            create_archive(&self.backup_dir, &self.archive_path)?;
+            std::fs::remove_dir_all(&self.backup_dir)
+                .context(messages::delete_backup_dir_failed(&self.backup_dir))?;
+        }
+        Ok(())
+    }
+
+    /// 备份目录中是否有索引以外的文件
+    fn has_original_files(&self) -> bool {
+        WalkDir::new(&self.backup_dir)
+            .into_iter()
+            .filter_map(Result::ok)
+            .any(|entry| entry.file_type().is_file() && entry.file_name() != INDEX_FILE_NAME)
+    }
+
+    fn relative_path<'a>(&self, path: &'a Path) -> anyhow::Result<&'a Path> {
+        let relative_path = path
+            .strip_prefix(&self.manga_dir)
+            .context(messages::not_parent_dir(&self.manga_dir, path))?;
+        Ok(relative_path)
+    }
+}
+
+/// 返回漫画目录`manga_dir`的(备份目录, 备份压缩包)路径
+fn backup_paths(manga_dir: &Path) -> anyhow::Result<(PathBuf, PathBuf)> {
+    let manga_name = manga_dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(anyhow!(messages::manga_name_failed(manga_dir)))?;
+    let backup_dir = manga_dir.with_file_name(format!("{manga_name}.原图备份"));
+    let archive_path = manga_dir.with_file_name(format!("{manga_name}.原图备份.zip"));
+    Ok((backup_dir, archive_path))
+}
+
+/// 移动文件，不能直接重命名时(比如跨分区)先复制再删除
+fn move_file(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).context(messages::create_dir_failed(parent))?;
+    }
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to).context(messages::copy_failed(from, to))?;
+        std::fs::remove_file(from).context(messages::delete_failed(from))?;
+    }
+    Ok(())
+}
+
+/// 把压缩包`archive_path`解压到`backup_dir`，已存在的文件不会被覆盖
+fn extract_archive(archive_path: &Path, backup_dir: &Path) -> anyhow::Result<()> {
+    let file =
+        File::open(archive_path).context(messages::open_backup_archive_failed(archive_path))?;
+    let mut archive =
+        ZipArchive::new(file).context(messages::read_backup_archive_failed(archive_path))?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let Some(relative_path) = entry.enclosed_name() else {
+            continue;
+        };
+        let path = backup_dir.join(relative_path);
+        if entry.is_dir() || path.exists() {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(messages::create_dir_failed(parent))?;
+        }
+        let mut file = File::create(&path).context(messages::create_file_failed(&path))?;
+        std::io::copy(&mut entry, &mut file).context(messages::extract_failed(&path))?;
+    }
+    Ok(())
+}
+
+/// 读取压缩包`archive_path`中的索引，不解压其他文件，压缩包中没有索引时返回`None`
+fn read_archive_index(archive_path: &Path) -> anyhow::Result<Option<String>> {
+    let file =
+        File::open(archive_path).context(messages::open_backup_archive_failed(archive_path))?;
+    let mut archive =
+        ZipArchive::new(file).context(messages::read_backup_archive_failed(archive_path))?;
+    let Ok(mut entry) = archive.by_name(INDEX_FILE_NAME) else {
+        return Ok(None);
+    };
+    let mut index_string = String::new();
+    entry
+        .read_to_string(&mut index_string)
+        .context(messages::read_backup_archive_failed(archive_path))?;
+    Ok(Some(index_string))
+}
+
+/// 把`backup_dir`打包成压缩包`archive_path`，先写入临时文件，成功后再替换旧的压缩包
+fn create_archive(backup_dir: &Path, archive_path: &Path) -> anyhow::Result<()> {
+    let tmp_path = archive_path.with_extension("zip.tmp");
+    let file = File::create(&tmp_path).context(messages::create_file_failed(&tmp_path))?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    // 图片本身已经是压缩过的，所以不再压缩
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    for entry in WalkDir::new(backup_dir).into_iter().filter_map(Result::ok) {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        let name = path
+            .strip_prefix(backup_dir)
+            .context(messages::not_parent_dir(backup_dir, path))?
+            .to_str()
+            .ok_or(anyhow!(messages::non_utf8_path(path)))?
+            .replace('\\', "/");
+        zip.start_file(name, options)?;
+        let mut file = File::open(path).context(messages::open_file_failed(path))?;
+        std::io::copy(&mut file, &mut zip).context(messages::compress_failed(path))?;
+    }
+    let mut writer = zip
+        .finish()
+        .context(messages::write_backup_archive_failed(&tmp_path))?;
+    writer.flush()?;
+    drop(writer);
+    std::fs::rename(&tmp_path, archive_path)
+        .context(messages::rename_failed(&tmp_path, archive_path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在临时目录中创建漫画目录`漫画/第1话`，其中有`1.jpg`和`2.webp`两张原图片，返回(临时目录, 漫画目录)
+    fn create_manga_dir() -> anyhow::Result<(PathBuf, PathBuf)> {
+        let root = std::env::temp_dir().join(format!("backup-test-{}", uuid::Uuid::new_v4()));
+        let manga_dir = root.join("漫画");
+        let chapter_dir = manga_dir.join("第1话");
+        std::fs::create_dir_all(&chapter_dir)?;
+        std::fs::write(chapter_dir.join("1.jpg"), "原图1")?;
+        std::fs::write(chapter_dir.join("2.webp"), "原图2")?;
+        Ok((root, manga_dir))
+    }
+
+    /// 模拟原地去水印：`1.jpg`处理完成，`2.webp`备份后还没写入输出就出错了，出错后依然调用`finish`
+    fn backup_then_fail(manga_dir: &Path, mode: BackupMode) -> anyhow::Result<()> {
+        let chapter_dir = manga_dir.join("第1话");
+        let backup = Backup::open(manga_dir, mode)?;
+        backup.backup_original(&chapter_dir.join("1.jpg"))?;
+        std::fs::write(chapter_dir.join("1.jpg"), "去水印1")?;
+        backup.backup_original(&chapter_dir.join("2.webp"))?;
+        backup.record_output(&chapter_dir.join("2.webp"), &chapter_dir.join("2.jpg"))?;
+        backup.finish()
+    }
+
+    fn read(path: &Path) -> anyhow::Result<String> {
+        Ok(std::fs::read_to_string(path)?)
+    }
+
+    fn backup_fail_restore(mode: BackupMode) -> anyhow::Result<()> {
+        let (root, manga_dir) = create_manga_dir()?;
+        let chapter_dir = manga_dir.join("第1话");
+        backup_then_fail(&manga_dir, mode)?;
+        assert_eq!(Backup::existing_mode(&manga_dir)?, Some(mode));
+        assert!(!chapter_dir.join("2.webp").exists());
+
+        let backup = Backup::open(&manga_dir, mode)?;
+        let restored_paths = backup.restore(&manga_dir)?;
+        backup.finish()?;
+        assert_eq!(restored_paths.len(), 2);
+        assert_eq!(read(&chapter_dir.join("1.jpg"))?, "原图1");
+        assert_eq!(read(&chapter_dir.join("2.webp"))?, "原图2");
+        assert_eq!(Backup::existing_mode(&manga_dir)?, None);
+
+        std::fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    fn restore_fail_retry(mode: BackupMode) -> anyhow::Result<()> {
+        let (root, manga_dir) = create_manga_dir()?;
+        let chapter_dir = manga_dir.join("第1话");
+        backup_then_fail(&manga_dir, mode)?;
+        // `2.jpg`是目录，删除它会失败，所以恢复完`1.jpg`后出错
+        std::fs::create_dir(chapter_dir.join("2.jpg"))?;
+        let backup = Backup::open(&manga_dir, mode)?;
+        let result = backup.restore(&manga_dir);
+        assert!(result.is_err());
+        // 即使没有调用`finish`，磁盘上的索引中也不再有已经恢复的`1.jpg`
+        let (backup_dir, _) = backup_paths(&manga_dir)?;
+        let index: BTreeMap<PathBuf, PathBuf> =
+            serde_json::from_str(&read(&backup_dir.join(INDEX_FILE_NAME))?)?;
+        assert_eq!(
+            index.keys().collect::<Vec<_>>(),
+            vec![Path::new("第1话/2.jpg")]
+        );
+        backup.finish()?;
+        assert_eq!(read(&chapter_dir.join("1.jpg"))?, "原图1");
+
+        // 再次恢复时不能删除已经恢复的`1.jpg`
+        std::fs::remove_dir(chapter_dir.join("2.jpg"))?;
+        let backup = Backup::open(&manga_dir, mode)?;
+        let restored_paths = backup.restore(&manga_dir)?;
+        backup.finish()?;
+        assert_eq!(restored_paths, vec![chapter_dir.join("2.webp")]);
+        assert_eq!(read(&chapter_dir.join("1.jpg"))?, "原图1");
+        assert_eq!(read(&chapter_dir.join("2.webp"))?, "原图2");
+        assert_eq!(Backup::existing_mode(&manga_dir)?, None);
+
+        std::fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn folder_backup_fail_restore() -> anyhow::Result<()> {
+        backup_fail_restore(BackupMode::Folder)
+    }
+
+    #[test]
+    fn archive_backup_fail_restore() -> anyhow::Result<()> {
+        backup_fail_restore(BackupMode::Archive)
+    }
+
+    #[test]
+    fn folder_restore_fail_retry() -> anyhow::Result<()> {
+        restore_fail_retry(BackupMode::Folder)
+    }
+
+    #[test]
+    fn archive_restore_fail_retry() -> anyhow::Result<()> {
+        restore_fail_retry(BackupMode::Archive)
+    }
+}
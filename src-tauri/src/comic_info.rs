@@ -0,0 +1,178 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::messages;
+use crate::natural_sort;
+use crate::utils::escape_xml;
+
+/// `ComicInfo.xml`中需要的信息，Komga和Kavita等媒体服务器会读取它
+pub struct ComicInfo {
+    pub series: String,
+    pub number: Option<String>,
+    pub title: Option<String>,
+    pub page_count: u32,
+}
+
+impl ComicInfo {
+    /// 根据漫画名`series`、章节目录名`chapter_name`和图片数量`page_count`构建`ComicInfo`
+    pub fn new(series: &str, chapter_name: &str, page_count: u32) -> Self {
+        let (number, title) = parse_chapter_name(chapter_name);
+        Self {
+            series: series.to_string(),
+            number,
+            title,
+            page_count,
+        }
+    }
+
+    pub fn to_xml(&self) -> String {
+        use std::fmt::Write;
+        let mut xml = String::new();
+        let _ = writeln!(xml, r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        let _ = writeln!(
+            xml,
+            r#"<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xmlns:xsd="http://www.w3.org/2001/XMLSchema">"#
+        );
+        if let Some(title) = &self.title {
+            let _ = writeln!(xml, "  <Title>{}</Title>", escape_xml(title));
+        }
+        let _ = writeln!(xml, "  <Series>{}</Series>", escape_xml(&self.series));
+        if let Some(number) = &self.number {
+            let _ = writeln!(xml, "  <Number>{}</Number>", escape_xml(number));
+        }
+        let _ = writeln!(xml, "  <PageCount>{}</PageCount>", self.page_count);
+        let _ = writeln!(xml, "  <LanguageISO>zh</LanguageISO>");
+        let _ = writeln!(xml, "  <Manga>Yes</Manga>");
+        let _ = writeln!(xml, "</ComicInfo>");
+        xml
+    }
+
+    /// 将`ComicInfo.xml`写入到章节目录`chapter_dir`
+    pub fn save(&self, chapter_dir: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(chapter_dir).context(messages::create_dir_failed(chapter_dir))?;
+        let path = chapter_dir.join("ComicInfo.xml");
+        std::fs::write(&path, self.to_xml()).context(messages::write_file_failed(&path))?;
+        Ok(())
+    }
+}
+
+/// 从章节目录名中解析出(话数, 标题)
+/// # Example
+/// - `第10话 标题` => (`10`, `标题`)
+/// - `第1.5話` => (`1.5`, None)
+/// - `第十二话` => (`12`, None)
+/// - `第一卷 第2话 标题` => (`2`, `标题`)
+/// - `12 标题` => (`12`, `标题`)
+/// - `番外` => (None, `番外`)
+fn parse_chapter_name(chapter_name: &str) -> (Option<String>, Option<String>) {
+    let name = chapter_name.trim();
+    // 优先匹配`第N话`这种格式，其次匹配以数字开头的格式
+    // 名字中有多个`第N`时(如`第一卷 第2话`)，优先使用后面跟着`话`、`章`等的那个，没有时使用最后一个
+    let numbered: Vec<(String, &str)> = name
+        .match_indices('第')
+        .filter_map(|(index, _)| split_any_number(&name[index + '第'.len_utf8()..]))
+        .collect();
+    let after_prefix = numbered
+        .iter()
+        .find(|(_, rest)| rest.starts_with(CHAPTER_SUFFIXES))
+        .or(numbered.last())
+        .map(|(number, rest)| {
+            (
+                number.clone(),
+                rest.trim_start_matches(['话', '話', '章', '回', '卷', '集']),
+            )
+        });
+    let Some((number, rest)) = after_prefix
+        .or_else(|| split_number(name).map(|(number, rest)| (number.to_string(), rest)))
+    else {
+        return (None, Some(name.to_string()));
+    };
+    let title = rest
+        .trim_start_matches(|c: char| c.is_whitespace() || "-_.:：、".contains(c))
+        .trim();
+    let title = (!title.is_empty()).then(|| title.to_string());
+    (Some(number), title)
+}
+
+/// `第N`后面表示章节(而不是卷)的字
+const CHAPTER_SUFFIXES: [char; 5] = ['话', '話', '章', '回', '集'];
+
+/// 将`s`开头的数字(半角数字或中文数字)与剩余部分分开，`s`不以数字开头时返回`None`
+fn split_any_number(s: &str) -> Option<(String, &str)> {
+    split_number(s)
+        .map(|(number, rest)| (number.to_string(), rest))
+        .or_else(|| {
+            natural_sort::split_cjk_number(s).map(|(number, rest)| (number.to_string(), rest))
+        })
+}
+
+/// 将`s`开头的数字(可以是小数)与剩余部分分开，`s`不以数字开头时返回`None`
+fn split_number(s: &str) -> Option<(&str, &str)> {
+    let number_len = s
+        .char_indices()
+        .take_while(|(i, c)| c.is_ascii_digit() || (*c == '.' && *i > 0))
+        .count();
+    // 数字和小数点都是单字节字符，所以字符数就是字节数
+    let number = s[..number_len].trim_end_matches('.');
+    if number.is_empty() {
+        return None;
+    }
+    Some((number, &s[number.len()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(number: Option<&str>, title: Option<&str>) -> (Option<String>, Option<String>) {
+        (number.map(str::to_string), title.map(str::to_string))
+    }
+
+    #[test]
+    fn ascii_numbers() {
+        assert_eq!(
+            parse_chapter_name("第10话 标题"),
+            parsed(Some("10"), Some("标题"))
+        );
+        assert_eq!(parse_chapter_name("第1.5話"), parsed(Some("1.5"), None));
+        assert_eq!(
+            parse_chapter_name("12 - 标题"),
+            parsed(Some("12"), Some("标题"))
+        );
+    }
+
+    #[test]
+    fn cjk_numbers() {
+        assert_eq!(parse_chapter_name("第十二话"), parsed(Some("12"), None));
+        assert_eq!(
+            parse_chapter_name("第一百零五章 标题"),
+            parsed(Some("105"), Some("标题"))
+        );
+        // 标题中的中文数字不算话数
+        assert_eq!(
+            parse_chapter_name("第三话 一个人"),
+            parsed(Some("3"), Some("一个人"))
+        );
+    }
+
+    #[test]
+    fn multiple_prefixes() {
+        assert_eq!(parse_chapter_name("第一卷 第2话"), parsed(Some("2"), None));
+        assert_eq!(
+            parse_chapter_name("第1卷 第十话 标题"),
+            parsed(Some("10"), Some("标题"))
+        );
+        // 只有卷数时使用卷数
+        assert_eq!(
+            parse_chapter_name("第3卷 番外"),
+            parsed(Some("3"), Some("番外"))
+        );
+    }
+
+    #[test]
+    fn no_number() {
+        assert_eq!(parse_chapter_name("番外"), parsed(None, Some("番外")));
+        assert_eq!(parse_chapter_name("次第"), parsed(None, Some("次第")));
+    }
+}
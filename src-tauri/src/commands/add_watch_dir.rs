@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use parking_lot::RwLock;
+use tauri::State;
+
+use crate::config::Config;
+use crate::errors::CommandResult;
+use crate::types::{ConflictPolicy, ImageFormat, WatchState, WatchTarget};
+use crate::watcher::MangaWatcher;
+
+/// 监视漫画目录`manga_dir`，有新图片时使用这些设置和背景水印图自动去水印，
+/// `backgrounds`中的每个元素是(黑色背景水印图路径, 白色背景水印图路径)
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+#[allow(clippy::too_many_arguments)]
+pub fn add_watch_dir(
+    config: State<RwLock<Config>>,
+    watcher: State<MangaWatcher>,
+    manga_dir: PathBuf,
+    output_dir: PathBuf,
+    format: ImageFormat,
+    optimize: bool,
+    backgrounds: Vec<(PathBuf, PathBuf)>,
+    comic_info: bool,
+    conflict_policy: ConflictPolicy,
+) -> CommandResult<WatchState> {
+    let target = WatchTarget {
+        manga_dir,
+        output_dir,
+        format,
+        optimize,
+        backgrounds,
+        comic_info,
+        conflict_policy,
+        output_settings: Some(config.read().output_settings()),
+    };
+    watcher.add_target(target)?;
+    Ok(watcher.state())
+}
@@ -0,0 +1,12 @@
+use tauri::{AppHandle, State};
+
+use crate::errors::CommandResult;
+use crate::queue::JobQueue;
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn clear_finished_jobs(app: AppHandle, queue: State<JobQueue>) -> CommandResult<()> {
+    queue.clear_finished(&app)?;
+    Ok(())
+}
@@ -0,0 +1,12 @@
+use tauri::State;
+
+use crate::errors::CommandResult;
+use crate::scan_cache::ScanCache;
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn clear_scan_cache(scan_cache: State<ScanCache>) -> CommandResult<()> {
+    scan_cache.clear()?;
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use tauri::{AppHandle, State};
+
+use crate::errors::CommandResult;
+use crate::queue::JobQueue;
+use crate::types::{ConflictPolicy, ImageFormat, QueueJob};
+
+/// 将漫画目录`manga_dir`的去水印任务加入队列，`backgrounds`中的每个元素是(黑色背景水印图路径, 白色背景水印图路径)
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+#[allow(clippy::too_many_arguments)]
+pub fn enqueue_job(
+    app: AppHandle,
+    queue: State<JobQueue>,
+    manga_dir: PathBuf,
+    output_dir: PathBuf,
+    format: ImageFormat,
+    optimize: bool,
+    backgrounds: Vec<(PathBuf, PathBuf)>,
+    comic_info: bool,
+    conflict_policy: ConflictPolicy,
+) -> CommandResult<QueueJob> {
+    let job = queue.enqueue(
+        &app,
+        manga_dir,
+        output_dir,
+        format,
+        optimize,
+        backgrounds,
+        comic_info,
+        conflict_policy,
+    )?;
+    Ok(job)
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use parking_lot::RwLock;
+use tauri::State;
+
+use crate::config::Config;
+use crate::errors::CommandResult;
+use crate::export;
+use crate::scan_cache::ScanCache;
+use crate::types::{ExportFormat, ExportScope};
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn export_manga(
+    config: State<RwLock<Config>>,
+    scan_cache: State<ScanCache>,
+    manga_dir: &str,
+    export_dir: &str,
+    format: ExportFormat,
+    scope: ExportScope,
+) -> CommandResult<Vec<PathBuf>> {
+    let manga_dir = PathBuf::from(manga_dir);
+    let export_dir = PathBuf::from(export_dir);
+    let manga_name = export::dir_name(&manga_dir)?;
+    let scan_options = config.read().scan_options.clone();
+    let export_paths = export::export_manga(
+        &manga_dir,
+        &manga_name,
+        &export_dir,
+        format,
+        scope,
+        &scan_options,
+        &scan_cache,
+    )?;
+    scan_cache.save()?;
+
+    Ok(export_paths)
+}
@@ -1,15 +1,17 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use anyhow::{anyhow, Context};
+use anyhow::Context;
 use image::RgbImage;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
-use tauri::AppHandle;
-use walkdir::WalkDir;
+use tauri::{AppHandle, State};
 
-use crate::errors::CommandResult;
-use crate::extensions::PathIsImage;
-use crate::types::RectData;
+use crate::config::Config;
+use crate::errors::{CommandResult, ErrorKind};
+use crate::messages;
+use crate::scan_cache::ScanCache;
+use crate::scanner;
+use crate::types::{MangaTree, RectData};
 use crate::utils;
 
 #[tauri::command(async)]
@@ -20,6 +22,8 @@ use crate::utils;
 #[allow(clippy::cast_precision_loss)]
 pub fn generate_background(
     app: AppHandle,
+    config: State<RwLock<Config>>,
+    scan_cache: State<ScanCache>,
     manga_dir: &str,
     rect_data: Option<RectData>,
     width: u32,
@@ -36,9 +40,12 @@ pub fn generate_background(
     let rect_data = rect_data.unwrap_or(default_rect_data);
 
     // 保证输出目录存在
-    std::fs::create_dir_all(&output_dir).context(format!("创建目录 {output_dir:?} 失败"))?;
+    std::fs::create_dir_all(&output_dir).context(messages::create_dir_failed(&output_dir))?;
+    let scan_options = config.read().scan_options.clone();
+    let manga_tree = scanner::scan_manga_dir(Path::new(manga_dir), &scan_options, &scan_cache)?;
     // 收集尺寸符合width和height的图片的路径
-    let image_paths = create_image_paths(manga_dir, width, height);
+    let image_paths = create_image_paths(&manga_tree, &scan_cache, width, height);
+    scan_cache.save()?;
     // 用于保存各种符合条件的背景水印图
     let backgrounds = Mutex::new(vec![]);
     // 用于标记是否找到了黑色和白色背景水印图
@@ -51,9 +58,7 @@ pub fn generate_background(
             return Ok(());
         }
 
-        let mut img = image::open(path)
-            .context(format!("打开图片 {path:?} 失败"))?
-            .to_rgb8();
+        let mut img = utils::decode_image(path)?.to_rgb8();
         // 如果图片不满足背景的条件，则直接跳过
         if !is_background(&img, &rect_data) {
             return Ok(());
@@ -99,7 +104,7 @@ pub fn generate_background(
         let black_output_path = output_dir.join("black.png");
         black
             .save(&black_output_path)
-            .context(format!("保存图片 {black_output_path:?} 失败",))?;
+            .context(messages::save_image_failed(&black_output_path))?;
     }
     // 如果找到了黑色和白色背景水印图
     if background_pair_found {
@@ -108,38 +113,47 @@ pub fn generate_background(
         let white_output_path = output_dir.join("white.png");
         white
             .save(&white_output_path)
-            .context(format!("保存图片 {white_output_path:?} 失败",))?;
+            .context(messages::save_image_failed(&white_output_path))?;
     }
 
+    tracing::info!(
+        manga_dir,
+        width,
+        height,
+        candidates = backgrounds.len(),
+        background_pair_found,
+        "{}",
+        messages::log_background_generated()
+    );
+    let missing = ErrorKind::BackgroundMissing { width, height };
     if backgrounds.is_empty() {
-        return Err(anyhow!("找不到尺寸为({width}x{height})的背景水印图\n").into());
+        return Err(missing
+            .error(messages::background_not_found(width, height))
+            .into());
     } else if !background_pair_found {
-        return Err(anyhow!("只找到一张尺寸为({width}x{height})的背景水印图\n").into());
+        return Err(missing
+            .error(messages::background_pair_incomplete(width, height))
+            .into());
     };
 
     Ok(())
 }
 
-/// 遍历`manga_dir`目录下的所有图片文件，收集尺寸符合`width`和`height`的图片的路径
+/// 遍历`manga_tree`中的所有图片，收集尺寸符合`width`和`height`的图片的路径
 #[allow(clippy::cast_possible_truncation)]
-fn create_image_paths(manga_dir: &str, width: u32, height: u32) -> Vec<PathBuf> {
-    let image_paths: Vec<PathBuf> = WalkDir::new(PathBuf::from(manga_dir))
-        .max_depth(2) // 一般第一层目录是章节目录，第二层目录是图片文件
+fn create_image_paths(
+    manga_tree: &MangaTree,
+    scan_cache: &ScanCache,
+    width: u32,
+    height: u32,
+) -> Vec<PathBuf> {
+    let pages: Vec<&PathBuf> = manga_tree.pages().collect();
+    let image_paths: Vec<PathBuf> = scan_cache
+        .probe_all(&pages)
         .into_iter()
-        .filter_map(Result::ok)
-        .filter_map(|entry| {
-            let path = entry.into_path();
-            if !path.is_file() || !path.is_image() {
-                return None;
-            }
-            // 只收集尺寸符合width和height的图片的路径
-            let (img_width, img_height) = image::image_dimensions(&path).ok()?;
-            if img_width == width && img_height == height {
-                Some(path)
-            } else {
-                None
-            }
-        })
+        // 只收集尺寸符合width和height的图片的路径
+        .filter(|info| info.width == width && info.height == height)
+        .map(|info| info.path)
         .collect();
     image_paths
 }
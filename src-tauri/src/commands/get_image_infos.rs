@@ -1,35 +1,29 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use walkdir::WalkDir;
+use parking_lot::RwLock;
+use tauri::State;
 
-use crate::{extensions::PathIsImage, types::ImageInfo};
+use crate::config::Config;
+use crate::errors::CommandResult;
+use crate::scan_cache::ScanCache;
+use crate::scanner;
+use crate::types::ImageInfo;
 
 #[tauri::command(async)]
 #[specta::specta]
 #[allow(clippy::cast_possible_truncation)]
-pub fn get_image_infos(manga_dir: &str) -> Vec<ImageInfo> {
-    // 用于存储图片的信息
-    let mut image_infos = vec![];
-    // 遍历漫画目录下的所有文件，获取图片的信息
-    WalkDir::new(PathBuf::from(manga_dir))
-        .max_depth(2) //  一般第一层目录是章节目录，第二层目录是图片文件
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter_map(|entry| {
-            let path = entry.into_path();
-            if !path.is_file() || !path.is_image() {
-                return None;
-            }
-            let size = image::image_dimensions(&path).ok()?;
-            Some((path, size))
-        })
-        .for_each(|(path, size)| {
-            image_infos.push(ImageInfo {
-                width: size.0,
-                height: size.1,
-                path,
-            });
-        });
+#[allow(clippy::needless_pass_by_value)]
+pub fn get_image_infos(
+    config: State<RwLock<Config>>,
+    scan_cache: State<ScanCache>,
+    manga_dir: &str,
+) -> CommandResult<Vec<ImageInfo>> {
+    let scan_options = config.read().scan_options.clone();
+    let manga_tree = scanner::scan_manga_dir(Path::new(manga_dir), &scan_options, &scan_cache)?;
+    // 获取漫画目录下所有图片的信息
+    let pages: Vec<&PathBuf> = manga_tree.pages().collect();
+    let image_infos = scan_cache.probe_all(&pages);
+    scan_cache.save()?;
 
-    image_infos
+    Ok(image_infos)
 }
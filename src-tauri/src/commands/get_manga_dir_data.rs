@@ -1,12 +1,14 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use tauri::AppHandle;
-use walkdir::WalkDir;
+use parking_lot::RwLock;
+use tauri::{AppHandle, State};
 
 use crate::commands::open_image::open_image;
+use crate::config::Config;
 use crate::errors::CommandResult;
-use crate::extensions::PathIsImage;
+use crate::scan_cache::ScanCache;
+use crate::scanner;
 use crate::types::MangaDirData;
 use crate::utils;
 
@@ -14,26 +16,27 @@ use crate::utils;
 #[specta::specta]
 #[allow(clippy::cast_possible_truncation)]
 #[allow(clippy::needless_pass_by_value)]
-pub fn get_manga_dir_data(app: AppHandle, manga_dir: &str) -> CommandResult<Vec<MangaDirData>> {
+pub fn get_manga_dir_data(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+    scan_cache: State<ScanCache>,
+    manga_dir: &str,
+) -> CommandResult<Vec<MangaDirData>> {
+    let scan_options = config.read().scan_options.clone();
+    let manga_tree = scanner::scan_manga_dir(Path::new(manga_dir), &scan_options, &scan_cache)?;
     // 用于存储不同尺寸的图片的数量
     let mut size_count: HashMap<(u32, u32), u32> = HashMap::new();
-    // 遍历漫画目录下的所有文件，统计不同尺寸的图片的数量
-    WalkDir::new(PathBuf::from(manga_dir))
-        .max_depth(2) // 一般第一层目录是章节目录，第二层目录是图片文件
+    // 并发获取漫画目录下所有图片的信息，统计不同尺寸的图片的数量
+    let pages: Vec<&PathBuf> = manga_tree.pages().collect();
+    scan_cache
+        .probe_all(&pages)
         .into_iter()
-        .filter_map(Result::ok)
-        .filter_map(|entry| {
-            let path = entry.into_path();
-            if !path.is_file() || !path.is_image() {
-                return None;
-            }
-            // imagesize::size(&path).ok()
-            image::image_dimensions(&path).ok()
-        })
+        .map(|info| (info.width, info.height))
         .for_each(|size| {
             let count = size_count.entry(size).or_insert(0);
             *count += 1;
         });
+    scan_cache.save()?;
     // 将统计结果转换为Vec<MangaDirData>
     let mut manga_dir_data: Vec<MangaDirData> = size_count
         .into_iter()
@@ -45,8 +48,12 @@ pub fn get_manga_dir_data(app: AppHandle, manga_dir: &str) -> CommandResult<Vec<
             white_background: None,
         })
         .collect();
-    // 以count降序排序
-    manga_dir_data.sort_by(|a, b| b.count.cmp(&a.count));
+    // 以count降序排序，count相同时按尺寸排序，保证顺序是确定的
+    manga_dir_data.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| (a.width, a.height).cmp(&(b.width, b.height)))
+    });
     // 获取背景水印图的数据
     for dir_data in &mut manga_dir_data {
         let width = dir_data.width;
@@ -0,0 +1,11 @@
+use tauri::State;
+
+use crate::queue::JobQueue;
+use crate::types::QueueState;
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn get_queue(queue: State<JobQueue>) -> QueueState {
+    queue.state()
+}
@@ -0,0 +1,11 @@
+use tauri::State;
+
+use crate::types::WatchState;
+use crate::watcher::MangaWatcher;
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn get_watch_state(watcher: State<MangaWatcher>) -> WatchState {
+    watcher.state()
+}
@@ -1,21 +1,51 @@
 pub mod prelude {
     pub use crate::commands::{
+        add_watch_dir::add_watch_dir, clear_finished_jobs::clear_finished_jobs,
+        clear_scan_cache::clear_scan_cache, enqueue_job::enqueue_job, export_manga::export_manga,
         generate_background::generate_background,
         get_background_dir_abs_path::get_background_dir_abs_path,
         get_background_dir_relative_path::get_background_dir_relative_path, get_config::get_config,
         get_image_infos::get_image_infos, get_manga_dir_data::get_manga_dir_data,
-        open_image::open_image, remove_watermark::remove_watermark, save_config::save_config,
-        show_path_in_file_manager::show_path_in_file_manager,
+        get_queue::get_queue, get_watch_state::get_watch_state, move_job::move_job,
+        open_image::open_image, open_log_dir::open_log_dir, pause_queue::pause_queue,
+        plan_remove_watermark::plan_remove_watermark, read_log::read_log, remove_job::remove_job,
+        remove_watch_dir::remove_watch_dir, remove_watermark::remove_watermark,
+        restore_originals::restore_originals, retry_failed::retry_failed, retry_job::retry_job,
+        save_config::save_config, save_job_report::save_job_report, scan_manga_dir::scan_manga_dir,
+        set_watch_enabled::set_watch_enabled, show_path_in_file_manager::show_path_in_file_manager,
+        start_queue::start_queue, stream_image_infos::stream_image_infos,
     };
 }
 
+mod add_watch_dir;
+mod clear_finished_jobs;
+mod clear_scan_cache;
+mod enqueue_job;
+mod export_manga;
 mod generate_background;
 mod get_background_dir_abs_path;
 mod get_background_dir_relative_path;
 mod get_config;
 mod get_image_infos;
 mod get_manga_dir_data;
+mod get_queue;
+mod get_watch_state;
+mod move_job;
 mod open_image;
+mod open_log_dir;
+mod pause_queue;
+mod plan_remove_watermark;
+mod read_log;
+mod remove_job;
+mod remove_watch_dir;
 mod remove_watermark;
+mod restore_originals;
+mod retry_failed;
+mod retry_job;
 mod save_config;
+mod save_job_report;
+mod scan_manga_dir;
+mod set_watch_enabled;
 mod show_path_in_file_manager;
+mod start_queue;
+mod stream_image_infos;
@@ -0,0 +1,12 @@
+use tauri::{AppHandle, State};
+
+use crate::errors::CommandResult;
+use crate::queue::JobQueue;
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn move_job(app: AppHandle, queue: State<JobQueue>, id: &str, index: u32) -> CommandResult<()> {
+    queue.move_job(&app, id, index as usize)?;
+    Ok(())
+}
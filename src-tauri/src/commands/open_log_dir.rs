@@ -0,0 +1,14 @@
+use tauri::AppHandle;
+
+use crate::errors::CommandResult;
+use crate::logger;
+
+/// 在文件管理器中打开日志目录，并选中正在写入的日志文件
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn open_log_dir(app: AppHandle) -> CommandResult<()> {
+    let log_path = logger::log_path(&app)?;
+    showfile::show_path_in_file_manager(log_path);
+    Ok(())
+}
@@ -0,0 +1,13 @@
+use tauri::{AppHandle, State};
+
+use crate::errors::CommandResult;
+use crate::queue::JobQueue;
+
+/// 暂停队列，正在执行的任务会继续执行完
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn pause_queue(app: AppHandle, queue: State<JobQueue>) -> CommandResult<()> {
+    queue.pause(&app)?;
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use parking_lot::RwLock;
+use tauri::State;
+
+use crate::backup::Backup;
+use crate::config::Config;
+use crate::errors::CommandResult;
+use crate::manifest::Manifest;
+use crate::remover::{self, Backgrounds, ProcessingResources, RemoveWatermarkOptions};
+use crate::scan_cache::ScanCache;
+use crate::types::{ConflictPolicy, ImageData, ImageFormat, RemoveWatermarkPlan};
+
+/// 预览去水印的结果，返回每张图片会被如何处理，不会写入任何图片
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+#[allow(clippy::too_many_arguments)]
+pub fn plan_remove_watermark(
+    config: State<RwLock<Config>>,
+    scan_cache: State<ScanCache>,
+    resources: State<ProcessingResources>,
+    manga_dir: &str,
+    output_dir: &str,
+    format: ImageFormat,
+    optimize: bool,
+    backgrounds_data: Vec<(ImageData, ImageData)>,
+    comic_info: bool,
+    conflict_policy: ConflictPolicy,
+) -> CommandResult<RemoveWatermarkPlan> {
+    let options = RemoveWatermarkOptions::new(
+        &config.read(),
+        Path::new(manga_dir),
+        Path::new(output_dir),
+        format,
+        optimize,
+        comic_info,
+        conflict_policy,
+    );
+    let backgrounds = Backgrounds::new(&backgrounds_data)?;
+    let manifest = Manifest::load(&options.manga_dir, &options.output_dir, options.in_place);
+    // 只读地打开已有的备份，预览时不能修改备份，否则可能与正在原地处理的任务冲突
+    let backup = if options.in_place {
+        Backup::open_read_only(&options.manga_dir)?
+    } else {
+        None
+    };
+    // 检查图片时也会并行读取，和去水印使用同一个线程池
+    let pool = resources.pool(options.worker_threads)?;
+    let plan = pool.install(|| {
+        remover::plan(
+            &options,
+            &backgrounds,
+            &scan_cache,
+            &manifest,
+            backup.as_ref(),
+        )
+    })?;
+    scan_cache.save()?;
+
+    Ok(plan)
+}
@@ -0,0 +1,14 @@
+use tauri::AppHandle;
+
+use crate::errors::CommandResult;
+use crate::logger;
+use crate::types::LogEntry;
+
+/// 最近的`count`条日志，按时间从旧到新排列
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn read_log(app: AppHandle, count: u32) -> CommandResult<Vec<LogEntry>> {
+    let entries = logger::read_recent(&app, count as usize)?;
+    Ok(entries)
+}
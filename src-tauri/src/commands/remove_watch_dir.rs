@@ -0,0 +1,18 @@
+use std::path::Path;
+
+use tauri::State;
+
+use crate::errors::CommandResult;
+use crate::types::WatchState;
+use crate::watcher::MangaWatcher;
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn remove_watch_dir(
+    watcher: State<MangaWatcher>,
+    manga_dir: &str,
+) -> CommandResult<WatchState> {
+    watcher.remove_target(Path::new(manga_dir))?;
+    Ok(watcher.state())
+}
@@ -0,0 +1,29 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+
+use crate::backup::Backup;
+use crate::errors::CommandResult;
+use crate::messages;
+
+/// 撤销原地去水印，将漫画目录`manga_dir`中的图片恢复为备份中的原图片，
+/// `chapter_dir`不为`None`时只恢复该章节
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn restore_originals(
+    manga_dir: &str,
+    chapter_dir: Option<String>,
+) -> CommandResult<Vec<PathBuf>> {
+    let manga_dir = Path::new(manga_dir);
+    let mode = Backup::existing_mode(manga_dir)?
+        .ok_or(anyhow!(messages::no_original_backup(manga_dir)))?;
+    // 恢复后剩余的备份保持原来的备份模式
+    let backup = Backup::open(manga_dir, mode)?;
+    let scope_dir = chapter_dir.as_deref().map_or(manga_dir, Path::new);
+    let restored_paths = backup.restore(scope_dir);
+    // 恢复中途出错时也要保存索引，压缩包模式下还要重新打包，否则已恢复的原图片下次会被再次解压回备份中
+    backup.finish()?;
+
+    Ok(restored_paths?)
+}
@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use parking_lot::RwLock;
+use tauri::{AppHandle, State};
+
+use crate::config::Config;
+use crate::errors::CommandResult;
+use crate::messages;
+use crate::remover::{self, Backgrounds, ProcessingResources, RemoveWatermarkOptions};
+use crate::report::{self, JobHistory};
+use crate::scan_cache::ScanCache;
+use crate::types::JobReport;
+
+/// 按照之前的设置，只重新处理上次去水印失败的图片，
+/// 上次的报告来自内存中id为`report_id`的报告，或者`report_path`处保存的JSON格式的报告
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn retry_failed(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+    scan_cache: State<ScanCache>,
+    history: State<JobHistory>,
+    resources: State<ProcessingResources>,
+    report_id: Option<String>,
+    report_path: Option<PathBuf>,
+) -> CommandResult<JobReport> {
+    let previous = match (report_id, report_path) {
+        (Some(report_id), _) => history.get(&report_id)?,
+        (None, Some(report_path)) => report::load(&report_path)?,
+        (None, None) => return Err(anyhow!(messages::no_report_specified()).into()),
+    };
+    let options = RemoveWatermarkOptions::retry(&config.read(), &previous)?;
+    let backgrounds = Backgrounds::open_for_report(&app, &previous)?;
+    // 同一部漫画正在队列或监视中去水印时直接拒绝
+    let _claim = resources
+        .claim(&options.manga_dir)
+        .ok_or(anyhow!(messages::manga_processing(&options.manga_dir)))?;
+    let outcome = remover::remove_watermark(&app, &options, &backgrounds, &scan_cache)?;
+
+    Ok(outcome.report)
+}
@@ -0,0 +1,13 @@
+use tauri::{AppHandle, State};
+
+use crate::errors::CommandResult;
+use crate::queue::JobQueue;
+
+/// 将失败的任务重新排队，队列正在执行时会自动开始，否则等下次开始队列
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn retry_job(app: AppHandle, queue: State<JobQueue>, id: &str) -> CommandResult<()> {
+    queue.retry_job(&app, id)?;
+    Ok(())
+}
@@ -3,6 +3,9 @@ use tauri::{AppHandle, State};
 
 use crate::config::Config;
 use crate::errors::CommandResult;
+use crate::messages;
+use crate::output_template;
+use crate::types::OutputLayout;
 
 #[tauri::command(async)]
 #[specta::specta]
@@ -12,8 +15,13 @@ pub fn save_config(
     config_state: State<RwLock<Config>>,
     config: Config,
 ) -> CommandResult<()> {
+    // 保存前检查模板，而不是等到去水印时才报错
+    if config.output_layout == OutputLayout::Template {
+        output_template::validate(&config.output_template)?;
+    }
     let mut config_state = config_state.write();
     *config_state = config;
+    messages::set_locale(config_state.locale);
     config_state.save(&app)?;
     Ok(())
 }
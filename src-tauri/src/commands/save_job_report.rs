@@ -0,0 +1,20 @@
+use std::path::PathBuf;
+
+use tauri::State;
+
+use crate::errors::CommandResult;
+use crate::report::{self, JobHistory};
+use crate::types::ReportFormat;
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn save_job_report(
+    history: State<JobHistory>,
+    report_id: &str,
+    format: ReportFormat,
+) -> CommandResult<PathBuf> {
+    let report = history.get(report_id)?;
+    let report_path = report::save(&report, format)?;
+    Ok(report_path)
+}
@@ -0,0 +1,24 @@
+use std::path::Path;
+
+use parking_lot::RwLock;
+use tauri::State;
+
+use crate::config::Config;
+use crate::errors::CommandResult;
+use crate::scan_cache::ScanCache;
+use crate::scanner;
+use crate::types::MangaTree;
+
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn scan_manga_dir(
+    config: State<RwLock<Config>>,
+    scan_cache: State<ScanCache>,
+    manga_dir: &str,
+) -> CommandResult<MangaTree> {
+    let scan_options = config.read().scan_options.clone();
+    let manga_tree = scanner::scan_manga_dir(Path::new(manga_dir), &scan_options, &scan_cache)?;
+    scan_cache.save()?;
+    Ok(manga_tree)
+}
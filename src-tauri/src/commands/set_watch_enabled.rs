@@ -0,0 +1,18 @@
+use tauri::{AppHandle, State};
+
+use crate::errors::CommandResult;
+use crate::types::WatchState;
+use crate::watcher::MangaWatcher;
+
+/// 开始或停止监视所有漫画目录
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn set_watch_enabled(
+    app: AppHandle,
+    watcher: State<MangaWatcher>,
+    enabled: bool,
+) -> CommandResult<WatchState> {
+    watcher.set_enabled(&app, enabled)?;
+    Ok(watcher.state())
+}
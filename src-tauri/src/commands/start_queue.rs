@@ -0,0 +1,20 @@
+use parking_lot::RwLock;
+use tauri::{AppHandle, State};
+
+use crate::config::Config;
+use crate::errors::CommandResult;
+use crate::queue::JobQueue;
+
+/// 开始执行队列中等待的任务，同时执行的任务数由配置中的`queue_concurrency`决定
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn start_queue(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+    queue: State<JobQueue>,
+) -> CommandResult<()> {
+    let concurrency = config.read().queue_concurrency;
+    queue.start(&app, concurrency)?;
+    Ok(())
+}
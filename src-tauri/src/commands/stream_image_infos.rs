@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use parking_lot::RwLock;
+use tauri::{AppHandle, State};
+use tauri_specta::Event;
+
+use crate::config::Config;
+use crate::errors::CommandResult;
+use crate::events;
+use crate::natural_sort;
+use crate::scan_cache::ScanCache;
+use crate::scanner;
+use crate::types::SizeCount;
+
+/// 每批处理的图片数量
+const BATCH_SIZE: usize = 256;
+
+/// `get_image_infos`的流式版本，边遍历漫画目录边获取图片的信息，每找到一批图片就发送一次`ScanBatchEvent`，
+/// 全部获取完后发送`ScanEndEvent`，让前端在超大的漫画目录中也能尽快显示结果
+#[tauri::command(async)]
+#[specta::specta]
+#[allow(clippy::needless_pass_by_value)]
+pub fn stream_image_infos(
+    app: AppHandle,
+    config: State<RwLock<Config>>,
+    scan_cache: State<ScanCache>,
+    manga_dir: &str,
+) -> CommandResult<()> {
+    let scan_options = config.read().scan_options.clone();
+    let manga_dir = Path::new(manga_dir);
+    let mut batcher = ScanBatcher {
+        app: &app,
+        scan_cache: &scan_cache,
+        manga_dir,
+        pending: Vec::with_capacity(BATCH_SIZE),
+        size_count: HashMap::new(),
+        scanned: 0,
+        total: 0,
+    };
+    // 遍历的回调中无法返回错误，记录第一个错误，之后的图片不再处理
+    let mut batch_result = Ok(());
+    scanner::walk_images(manga_dir, &scan_options, &scan_cache, |path| {
+        if batch_result.is_ok() {
+            batch_result = batcher.push(path);
+        }
+    })?;
+    batch_result?;
+    batcher.flush()?;
+    scan_cache.save()?;
+    // 发送ScanEndEvent事件
+    let payload = events::ScanEndEventPayload {
+        manga_dir: manga_dir.to_path_buf(),
+        total: batcher.total,
+    };
+    let event = events::ScanEndEvent(payload);
+    event.emit(&app).map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+/// 收集遍历时找到的图片，凑够一批后获取它们的信息并发送`ScanBatchEvent`
+struct ScanBatcher<'a> {
+    app: &'a AppHandle,
+    scan_cache: &'a ScanCache,
+    manga_dir: &'a Path,
+    // 还没获取信息的图片
+    pending: Vec<PathBuf>,
+    // (width, height) => count
+    size_count: HashMap<(u32, u32), u32>,
+    // 已经获取信息的图片数量
+    scanned: u32,
+    // 到目前为止找到的图片数量
+    total: u32,
+}
+
+impl ScanBatcher<'_> {
+    fn push(&mut self, path: PathBuf) -> anyhow::Result<()> {
+        self.pending.push(path);
+        self.total += 1;
+        if self.pending.len() >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// 获取还没获取信息的图片的信息，并发送`ScanBatchEvent`
+    #[allow(clippy::cast_possible_truncation)]
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        // 遍历的顺序取决于文件系统，每批图片都按自然顺序排序后再发送
+        self.pending
+            .sort_by(|a, b| natural_sort::compare_paths(a, b));
+        let batch: Vec<&PathBuf> = self.pending.iter().collect();
+        let image_infos = self.scan_cache.probe_all(&batch);
+        for info in &image_infos {
+            *self
+                .size_count
+                .entry((info.width, info.height))
+                .or_insert(0) += 1;
+        }
+        self.scanned += self.pending.len() as u32;
+        self.pending.clear();
+        // 发送ScanBatchEvent事件
+        let payload = events::ScanBatchEventPayload {
+            manga_dir: self.manga_dir.to_path_buf(),
+            image_infos,
+            size_counts: create_size_counts(&self.size_count),
+            scanned: self.scanned,
+            total: self.total,
+        };
+        let event = events::ScanBatchEvent(payload);
+        event.emit(self.app)?;
+        Ok(())
+    }
+}
+
+/// 将`size_count`转换为以count降序排序的`Vec<SizeCount>`
+fn create_size_counts(size_count: &HashMap<(u32, u32), u32>) -> Vec<SizeCount> {
+    let mut size_counts: Vec<SizeCount> = size_count
+        .iter()
+        .map(|(&(width, height), &count)| SizeCount {
+            width,
+            height,
+            count,
+        })
+        .collect();
+    // count相同时按尺寸排序，保证顺序是确定的
+    size_counts.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| (a.width, a.height).cmp(&(b.width, b.height)))
+    });
+    size_counts
+}
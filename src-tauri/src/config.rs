@@ -1,10 +1,17 @@
 use std::path::PathBuf;
 
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::{AppHandle, Manager};
+use tauri::AppHandle;
 
-use crate::types::ImageFormat;
+use crate::errors::{ErrorKind, ResultKindContext};
+use crate::messages;
+use crate::paths;
+use crate::types::{
+    BackupMode, ConflictPolicy, ExportFormat, ExportScope, ImageFormat, Locale, OutputLayout,
+    OutputSettings, PipelineThreads, ScanOptions,
+};
 
 #[allow(clippy::struct_field_names)]
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
@@ -13,32 +20,151 @@ pub struct Config {
     pub output_dir: PathBuf,
     pub output_format: ImageFormat,
     pub output_optimize: bool,
+    // 是否在每个输出章节目录中生成ComicInfo.xml
+    #[serde(default)]
+    pub output_comic_info: bool,
+    // 输出路径已存在文件时的处理方式
+    #[serde(default)]
+    pub output_conflict_policy: ConflictPolicy,
+    // 是否跳过原图片和设置都没有变化的图片(根据输出目录中的清单判断)
+    #[serde(default = "default_output_incremental")]
+    pub output_incremental: bool,
+    #[serde(default)]
+    pub output_layout: OutputLayout,
+    // output_layout为Template时使用的输出路径模板，可用的变量有
+    // {manga} {chapter} {chapter_index} {page} {stem} {ext}，数字变量可以指定宽度，如{page:04}
+    #[serde(default = "default_output_template")]
+    pub output_template: String,
+    // 是否原地去水印，为true时不使用output_dir，原图片会先备份再被覆盖
+    #[serde(default)]
+    pub in_place: bool,
+    #[serde(default)]
+    pub backup_mode: BackupMode,
+    // 去水印完成后导出的格式，为`None`时不导出
+    #[serde(default)]
+    pub export_format: Option<ExportFormat>,
+    #[serde(default)]
+    pub export_scope: ExportScope,
+    // 所有命令遍历漫画目录时都使用这个扫描规则
+    #[serde(default)]
+    pub scan_options: ScanOptions,
+    // 任务队列最多同时执行的任务数
+    #[serde(default = "default_queue_concurrency")]
+    pub queue_concurrency: u32,
+    // 监视模式下，章节目录在这么多秒内没有新的文件变化才开始处理
+    #[serde(default = "default_watch_settle_secs")]
+    pub watch_settle_secs: u64,
+    // 去水印使用的线程数，为0时使用CPU的逻辑核心数
+    #[serde(default)]
+    pub worker_threads: u32,
+    // 同时解码的图片最多占用多少MB内存(根据图片尺寸估算)，为0时不限制
+    #[serde(default = "default_memory_budget_mb")]
+    pub memory_budget_mb: u32,
+    // 去水印流水线中每个阶段的线程数，解码、去水印、编码阶段占用CPU的线程数依然不超过worker_threads
+    #[serde(default)]
+    pub pipeline_threads: PipelineThreads,
+    // 为true时单张图片处理失败不会停止去水印，失败的图片会在最后列出
+    #[serde(default)]
+    pub continue_on_error: bool,
+    // 错误信息等后端消息使用的语言
+    #[serde(default)]
+    pub locale: Locale,
 }
 
 impl Config {
     pub fn new(app: &AppHandle) -> anyhow::Result<Self> {
-        let resource_dir = app.path().resource_dir()?;
-        let config_path = resource_dir.join("config.json");
+        let config_path = paths::config_path(app)?;
         let default_config = Config {
-            output_dir: resource_dir,
+            output_dir: paths::data_dir(app)?,
             output_format: ImageFormat::Jpeg,
             output_optimize: false,
+            output_comic_info: false,
+            output_conflict_policy: ConflictPolicy::Overwrite,
+            output_incremental: true,
+            output_layout: OutputLayout::Mirrored,
+            output_template: default_output_template(),
+            in_place: false,
+            backup_mode: BackupMode::Folder,
+            export_format: None,
+            export_scope: ExportScope::Manga,
+            scan_options: ScanOptions::default(),
+            queue_concurrency: default_queue_concurrency(),
+            watch_settle_secs: default_watch_settle_secs(),
+            worker_threads: 0,
+            memory_budget_mb: default_memory_budget_mb(),
+            pipeline_threads: PipelineThreads::default(),
+            continue_on_error: false,
+            locale: Locale::ZhCn,
         };
         let config = if config_path.exists() {
-            let config_string = std::fs::read_to_string(config_path)?;
+            let config_string = std::fs::read_to_string(&config_path).kind_context(
+                ErrorKind::Config,
+                messages::read_config_failed(&config_path),
+            )?;
             serde_json::from_str(&config_string).unwrap_or(default_config)
         } else {
             default_config
         };
+        messages::set_locale(config.locale);
         config.save(app)?;
         Ok(config)
     }
 
+    pub fn output_settings(&self) -> OutputSettings {
+        OutputSettings {
+            layout: self.output_layout,
+            template: self.output_template.clone(),
+            incremental: self.output_incremental,
+            in_place: self.in_place,
+            backup_mode: self.backup_mode,
+            export_format: self.export_format,
+            export_scope: self.export_scope,
+        }
+    }
+
+    /// 复制一份配置，其中的输出设置替换为`settings`
+    pub fn with_output_settings(&self, settings: &OutputSettings) -> Self {
+        Self {
+            output_layout: settings.layout,
+            output_template: settings.template.clone(),
+            output_incremental: settings.incremental,
+            in_place: settings.in_place,
+            backup_mode: settings.backup_mode,
+            export_format: settings.export_format,
+            export_scope: settings.export_scope,
+            ..self.clone()
+        }
+    }
+
     pub fn save(&self, app: &AppHandle) -> anyhow::Result<()> {
-        let resource_dir = app.path().resource_dir()?;
-        let config_path = resource_dir.join("config.json");
+        let config_dir = paths::config_dir(app)?;
+        std::fs::create_dir_all(&config_dir).context(messages::create_dir_failed(&config_dir))?;
+        let config_path = config_dir.join(paths::CONFIG_FILE_NAME);
         let config_string = serde_json::to_string_pretty(self)?;
-        std::fs::write(config_path, config_string)?;
+        std::fs::write(&config_path, config_string).kind_context(
+            ErrorKind::Config,
+            messages::save_config_failed(&config_path),
+        )?;
         Ok(())
     }
 }
+
+fn default_output_incremental() -> bool {
+    true
+}
+
+fn default_output_template() -> String {
+    "{manga}/{chapter_index:03} {chapter}/{page:04}.{ext}".to_string()
+}
+
+fn default_queue_concurrency() -> u32 {
+    1
+}
+
+fn default_watch_settle_secs() -> u64 {
+    30
+}
+
+fn default_memory_budget_mb() -> u32 {
+    2048
+}
@@ -1,22 +1,201 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
 use serde::Serialize;
 use specta::Type;
 
 use crate::extensions::AnyhowErrorToStringChain;
+use crate::messages;
 
 pub type CommandResult<T> = Result<T, CommandError>;
 
-#[derive(Debug, Type)]
-pub struct CommandError(String);
-impl Serialize for CommandError {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_str(&format!("{:#}", self.0))
+/// 返回给前端的错误，`code`是固定不变的错误种类，前端可以根据它做不同的处理，`chain`是完整的错误链
+#[derive(Debug, Serialize, Type)]
+#[serde(tag = "code", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CommandError {
+    // 读写文件失败，`path`不为`None`时是出错的文件
+    Io {
+        path: Option<PathBuf>,
+        chain: String,
+    },
+    // 图片无法解码，通常是图片损坏或下载不完整
+    Decode {
+        path: PathBuf,
+        chain: String,
+    },
+    // 去水印后的图片编码失败
+    Encode {
+        path: PathBuf,
+        chain: String,
+    },
+    // 找不到尺寸为`width`x`height`的背景水印图
+    BackgroundMissing {
+        width: u32,
+        height: u32,
+        chain: String,
+    },
+    // 尺寸为`width`x`height`，但应该是`expected_width`x`expected_height`
+    #[serde(rename_all = "camelCase")]
+    SizeMismatch {
+        width: u32,
+        height: u32,
+        expected_width: u32,
+        expected_height: u32,
+        chain: String,
+    },
+    // 设置无效，例如输出路径模板或glob模式写错了
+    Config {
+        chain: String,
+    },
+    // 其他错误
+    Other {
+        chain: String,
+    },
+}
+
+/// 错误的种类和相关的字段，附加在`anyhow::Error`上，转换为`CommandError`时取出
+#[derive(Debug, Clone)]
+pub enum ErrorKind {
+    Io {
+        path: PathBuf,
+    },
+    Decode {
+        path: PathBuf,
+    },
+    Encode {
+        path: PathBuf,
+    },
+    BackgroundMissing {
+        width: u32,
+        height: u32,
+    },
+    SizeMismatch {
+        width: u32,
+        height: u32,
+        expected_width: u32,
+        expected_height: u32,
+    },
+    Config,
+}
+
+impl ErrorKind {
+    pub fn io(path: &Path) -> Self {
+        Self::Io {
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn decode(path: &Path) -> Self {
+        Self::Decode {
+            path: path.to_path_buf(),
+        }
     }
+
+    pub fn encode(path: &Path) -> Self {
+        Self::Encode {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// 创建一个种类为`self`的错误，`message`是错误信息
+    pub fn error(self, message: impl Into<String>) -> anyhow::Error {
+        anyhow::Error::msg(KindContext {
+            kind: self,
+            message: message.into(),
+        })
+    }
+}
+
+/// 带有错误种类的context，显示时只显示`message`，所以错误链与使用`context`时相同
+#[derive(Debug)]
+struct KindContext {
+    kind: ErrorKind,
+    message: String,
 }
+
+impl Display for KindContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+pub trait ResultKindContext<T> {
+    /// 与`anyhow::Context::context`相同，同时记录错误的种类`kind`
+    fn kind_context(self, kind: ErrorKind, message: impl Into<String>) -> anyhow::Result<T>;
+}
+
+impl<T, E> ResultKindContext<T> for Result<T, E>
+where
+    Self: anyhow::Context<T, E>,
+{
+    fn kind_context(self, kind: ErrorKind, message: impl Into<String>) -> anyhow::Result<T> {
+        anyhow::Context::context(
+            self,
+            KindContext {
+                kind,
+                message: message.into(),
+            },
+        )
+    }
+}
+
 impl From<anyhow::Error> for CommandError {
     fn from(err: anyhow::Error) -> Self {
-        Self(err.to_string_chain())
+        let chain = err.to_string_chain();
+        // 有多个种类时使用最外层的种类，没有记录种类的I/O错误也算作I/O错误
+        let kind = err
+            .downcast_ref::<KindContext>()
+            .map(|context| context.kind.clone());
+        let is_io = err.root_cause().downcast_ref::<std::io::Error>().is_some();
+        let command_error = match kind {
+            Some(ErrorKind::Io { path }) => Self::Io {
+                path: Some(path),
+                chain,
+            },
+            Some(ErrorKind::Decode { path }) => Self::Decode { path, chain },
+            Some(ErrorKind::Encode { path }) => Self::Encode { path, chain },
+            Some(ErrorKind::BackgroundMissing { width, height }) => Self::BackgroundMissing {
+                width,
+                height,
+                chain,
+            },
+            Some(ErrorKind::SizeMismatch {
+                width,
+                height,
+                expected_width,
+                expected_height,
+            }) => Self::SizeMismatch {
+                width,
+                height,
+                expected_width,
+                expected_height,
+                chain,
+            },
+            Some(ErrorKind::Config) => Self::Config { chain },
+            None if is_io => Self::Io { path: None, chain },
+            None => Self::Other { chain },
+        };
+        command_error.log();
+        command_error
+    }
+}
+
+impl CommandError {
+    /// 前端的错误提示关闭后信息就丢失了，所以返回给前端的错误都记录到日志中，
+    /// 只有意料之外的错误才使用error级别，用户操作就能解决的错误使用更低的级别
+    fn log(&self) {
+        match self {
+            // 还没生成背景水印图、尺寸不匹配是正常的流程，前端会提示用户处理
+            Self::BackgroundMissing { chain, .. } | Self::SizeMismatch { chain, .. } => {
+                tracing::debug!(err_msg = chain, "{}", messages::log_command_failed());
+            }
+            // 图片损坏和设置写错需要用户处理，但不是程序的问题
+            Self::Decode { chain, .. } | Self::Config { chain } => {
+                tracing::warn!(err_msg = chain, "{}", messages::log_command_failed());
+            }
+            Self::Io { chain, .. } | Self::Encode { chain, .. } | Self::Other { chain } => {
+                tracing::error!(err_msg = chain, "{}", messages::log_command_failed());
+            }
+        }
     }
 }
@@ -4,10 +4,13 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri_specta::Event;
 
+use crate::types::{ConflictResolution, ImageInfo, QueueState, SizeCount, StageStats};
+
 pub mod prelude {
     pub use crate::events::{
-        RemoveWatermarkEndEvent, RemoveWatermarkErrorEvent, RemoveWatermarkStartEvent,
-        RemoveWatermarkSuccessEvent,
+        PipelineStatsEvent, QueueUpdateEvent, RemoveWatermarkEndEvent, RemoveWatermarkErrorEvent,
+        RemoveWatermarkProgressEvent, RemoveWatermarkStartEvent, RemoveWatermarkSuccessEvent,
+        RemoveWatermarkSummaryEvent, ScanBatchEvent, ScanEndEvent, WatchErrorEvent,
     };
 }
 
@@ -26,6 +29,8 @@ pub struct RemoveWatermarkSuccessEventPayload {
     pub dir_path: PathBuf,
     pub img_path: PathBuf,
     pub current: u32,
+    // 输出路径已存在文件时实际的处理方式
+    pub conflict_resolution: ConflictResolution,
 }
 #[derive(Serialize, Deserialize, Clone, Type, Event)]
 pub struct RemoveWatermarkSuccessEvent(pub RemoveWatermarkSuccessEventPayload);
@@ -47,3 +52,87 @@ pub struct RemoveWatermarkEndEventPayload {
 }
 #[derive(Serialize, Deserialize, Clone, Type, Event)]
 pub struct RemoveWatermarkEndEvent(pub RemoveWatermarkEndEventPayload);
+
+#[derive(Serialize, Deserialize, Clone, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveWatermarkProgressEventPayload {
+    pub manga_dir: PathBuf,
+    // 已经处理完的图片数，包括跳过的图片
+    pub done: u32,
+    pub total: u32,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    // 最近一段时间的平均速度，不包括跳过的图片
+    pub pages_per_sec: f64,
+    pub read_bytes_per_sec: f64,
+    pub written_bytes_per_sec: f64,
+    pub elapsed_secs: f64,
+    // 预计剩余的秒数，速度为0时无法估计，为`None`
+    pub eta_secs: Option<f64>,
+}
+#[derive(Serialize, Deserialize, Clone, Type, Event)]
+pub struct RemoveWatermarkProgressEvent(pub RemoveWatermarkProgressEventPayload);
+
+#[derive(Serialize, Deserialize, Clone, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveWatermarkSummaryEventPayload {
+    pub manga_dir: PathBuf,
+    pub total: u32,
+    // 去除了水印的图片数
+    pub removed: u32,
+    // 没有对应尺寸的背景水印图而直接复制的图片数
+    pub copied: u32,
+    pub skipped: u32,
+    // 开启了出错后继续时处理失败的图片数
+    pub failed: u32,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub elapsed_secs: f64,
+}
+#[derive(Serialize, Deserialize, Clone, Type, Event)]
+pub struct RemoveWatermarkSummaryEvent(pub RemoveWatermarkSummaryEventPayload);
+
+#[derive(Serialize, Deserialize, Clone, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanBatchEventPayload {
+    pub manga_dir: PathBuf,
+    pub image_infos: Vec<ImageInfo>,
+    // 到目前为止各尺寸图片的数量
+    pub size_counts: Vec<SizeCount>,
+    // 已经获取信息的图片数量
+    pub scanned: u32,
+    // 到目前为止找到的图片数量，遍历还没结束时会继续增加
+    pub total: u32,
+}
+#[derive(Serialize, Deserialize, Clone, Type, Event)]
+pub struct ScanBatchEvent(pub ScanBatchEventPayload);
+
+#[derive(Serialize, Deserialize, Clone, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanEndEventPayload {
+    pub manga_dir: PathBuf,
+    pub total: u32,
+}
+#[derive(Serialize, Deserialize, Clone, Type, Event)]
+pub struct ScanEndEvent(pub ScanEndEventPayload);
+
+#[derive(Serialize, Deserialize, Clone, Type, Event)]
+pub struct QueueUpdateEvent(pub QueueState);
+
+#[derive(Serialize, Deserialize, Clone, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchErrorEventPayload {
+    pub manga_dir: PathBuf,
+    pub err_msg: String,
+}
+#[derive(Serialize, Deserialize, Clone, Type, Event)]
+pub struct WatchErrorEvent(pub WatchErrorEventPayload);
+
+#[derive(Serialize, Deserialize, Clone, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineStatsEventPayload {
+    pub manga_dir: PathBuf,
+    pub stages: Vec<StageStats>,
+}
+#[derive(Serialize, Deserialize, Clone, Type, Event)]
+pub struct PipelineStatsEvent(pub PipelineStatsEventPayload);
@@ -0,0 +1,223 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::export::ExportBook;
+use crate::messages;
+use crate::types::SourceFormat;
+use crate::utils::{self, escape_xml};
+
+/// EPUB中的一页
+struct EpubPage {
+    /// 页面序号，从1开始
+    index: usize,
+    width: u32,
+    height: u32,
+    image_href: String,
+    media_type: &'static str,
+}
+
+impl EpubPage {
+    fn image_id(&self) -> String {
+        format!("image-{:04}", self.index)
+    }
+
+    fn page_id(&self) -> String {
+        format!("page-{:04}", self.index)
+    }
+
+    fn page_href(&self) -> String {
+        format!("pages/{:04}.xhtml", self.index)
+    }
+}
+
+/// 将`book`写入为固定版式(fixed-layout)的EPUB 3文件
+pub fn write_epub(book: &ExportBook, path: &Path) -> anyhow::Result<()> {
+    let file = File::create(path).context(messages::create_file_failed(path))?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // mimetype必须是第一个文件，且不能压缩
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+    zip.start_file("META-INF/container.xml", deflated)?;
+    zip.write_all(CONTAINER_XML.as_bytes())?;
+
+    let mut pages = vec![];
+    // (章节名, 章节第一页的序号)
+    let mut toc = vec![];
+    for chapter in &book.chapters {
+        for (i, img_path) in chapter.pages.iter().enumerate() {
+            let index = pages.len() + 1;
+            if i == 0 {
+                toc.push((chapter.title.as_str(), index));
+            }
+            let (width, height) = utils::image_dimensions(img_path)?;
+            // EPUB阅读器不一定支持核心媒体类型以外的图片格式，这些格式的图片会被转换为png
+            let (format, data) = utils::read_web_compatible_image(img_path)?;
+            let (ext, media_type) = (format.extension(), media_type(format));
+            let page = EpubPage {
+                index,
+                width,
+                height,
+                image_href: format!("images/{index:04}.{ext}"),
+                media_type,
+            };
+            // 图片本身已经是压缩过的，所以不再压缩
+            zip.start_file(format!("OEBPS/{}", page.image_href), stored)?;
+            zip.write_all(&data)?;
+            zip.start_file(format!("OEBPS/{}", page.page_href()), deflated)?;
+            zip.write_all(create_page_xhtml(&book.title, &page).as_bytes())?;
+            pages.push(page);
+        }
+    }
+
+    zip.start_file("OEBPS/nav.xhtml", deflated)?;
+    zip.write_all(create_nav_xhtml(&book.title, &toc, &pages).as_bytes())?;
+    zip.start_file("OEBPS/content.opf", deflated)?;
+    zip.write_all(create_content_opf(&book.title, &pages).as_bytes())?;
+
+    let mut writer = zip.finish().context(messages::write_epub_failed(path))?;
+    writer.flush()?;
+    Ok(())
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn create_content_opf(title: &str, pages: &[EpubPage]) -> String {
+    let title = escape_xml(title);
+    let identifier = uuid::Uuid::new_v4();
+    let modified = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+    // 与导出时的界面语言一致
+    let language = messages::locale().language_tag();
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    for page in pages {
+        // 第一张图片作为封面
+        let properties = if page.index == 1 {
+            r#" properties="cover-image""#
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            manifest,
+            r#"    <item id="{}" href="{}" media-type="{}"{properties}/>"#,
+            page.image_id(),
+            page.image_href,
+            page.media_type,
+        );
+        let _ = writeln!(
+            manifest,
+            r#"    <item id="{}" href="{}" media-type="application/xhtml+xml"/>"#,
+            page.page_id(),
+            page.page_href(),
+        );
+        let _ = writeln!(spine, r#"    <itemref idref="{}"/>"#, page.page_id());
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">urn:uuid:{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>{language}</dc:language>
+    <meta property="dcterms:modified">{modified}</meta>
+    <meta property="rendition:layout">pre-paginated</meta>
+    <meta property="rendition:orientation">auto</meta>
+    <meta property="rendition:spread">none</meta>
+    <meta name="cover" content="image-0001"/>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#
+    )
+}
+
+fn create_nav_xhtml(title: &str, toc: &[(&str, usize)], pages: &[EpubPage]) -> String {
+    let title = escape_xml(title);
+    let mut items = String::new();
+    for (chapter_title, index) in toc {
+        let href = pages[index - 1].page_href();
+        let chapter_title = escape_xml(chapter_title);
+        let _ = writeln!(
+            items,
+            r#"      <li><a href="{href}">{chapter_title}</a></li>"#
+        );
+    }
+    let cover = escape_xml(&messages::epub_cover_label());
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+</head>
+<body>
+  <nav epub:type="toc" id="toc">
+    <h1>{title}</h1>
+    <ol>
+{items}    </ol>
+  </nav>
+  <nav epub:type="landmarks" hidden="">
+    <ol>
+      <li><a epub:type="cover" href="{}">{cover}</a></li>
+    </ol>
+  </nav>
+</body>
+</html>
+"#,
+        pages[0].page_href(),
+    )
+}
+
+fn create_page_xhtml(title: &str, page: &EpubPage) -> String {
+    let title = escape_xml(title);
+    let (width, height) = (page.width, page.height);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+  <style>html, body {{ margin: 0; padding: 0; }} img {{ display: block; width: {width}px; height: {height}px; }}</style>
+</head>
+<body>
+  <img src="../{}" alt=""/>
+</body>
+</html>
+"#,
+        page.image_href,
+    )
+}
+
+/// 根据图片的真实格式返回media-type
+fn media_type(format: SourceFormat) -> &'static str {
+    match format {
+        SourceFormat::Jpeg => "image/jpeg",
+        SourceFormat::Png => "image/png",
+        SourceFormat::Webp => "image/webp",
+        SourceFormat::Gif => "image/gif",
+        SourceFormat::Bmp => "image/bmp",
+        SourceFormat::Tiff => "image/tiff",
+        SourceFormat::Avif => "image/avif",
+        SourceFormat::Jxl => "image/jxl",
+    }
+}
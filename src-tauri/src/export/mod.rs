@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::messages;
+use crate::scan_cache::ScanCache;
+use crate::scanner;
+use crate::types::{ExportFormat, ExportScope, ScanOptions};
+
+mod epub;
+mod pdf;
+
+/// 导出时的一个章节，`title`用于生成目录
+pub struct ExportChapter {
+    pub title: String,
+    pub pages: Vec<PathBuf>,
+}
+
+/// 导出的一本书，可以是整部漫画，也可以是单个章节
+pub struct ExportBook {
+    pub title: String,
+    pub chapters: Vec<ExportChapter>,
+}
+
+impl ExportBook {
+    pub fn page_count(&self) -> usize {
+        self.chapters
+            .iter()
+            .map(|chapter| chapter.pages.len())
+            .sum()
+    }
+}
+
+/// 将已去水印的漫画目录`manga_dir`以`format`格式导出到`export_dir`，`title`是漫画名，返回导出的文件
+pub fn export_manga(
+    manga_dir: &Path,
+    title: &str,
+    export_dir: &Path,
+    format: ExportFormat,
+    scope: ExportScope,
+    scan_options: &ScanOptions,
+    scan_cache: &ScanCache,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let ext = match format {
+        ExportFormat::Epub => "epub",
+        ExportFormat::Pdf => "pdf",
+    };
+    let chapters = collect_chapters(manga_dir, scan_options, scan_cache)?;
+    if chapters.is_empty() {
+        return Err(anyhow!(messages::manga_dir_no_images(manga_dir)));
+    }
+
+    let export_paths = match scope {
+        ExportScope::Manga => {
+            // 整部漫画导出为一个文件(导出目录/漫画名.ext)
+            let export_path = export_dir.join(format!("{title}.{ext}"));
+            let book = ExportBook {
+                title: title.to_string(),
+                chapters,
+            };
+            export_book(&book, format, &export_path)?;
+            vec![export_path]
+        }
+        ExportScope::Chapter => {
+            // 每个章节导出为一个文件(导出目录/漫画名/章节名.ext)，并发导出
+            chapters
+                .into_par_iter()
+                .map(|chapter| -> anyhow::Result<PathBuf> {
+                    let export_path = export_dir
+                        .join(title)
+                        .join(format!("{}.{ext}", chapter.title));
+                    let book = ExportBook {
+                        title: format!("{title} {}", chapter.title),
+                        chapters: vec![chapter],
+                    };
+                    export_book(&book, format, &export_path)?;
+                    Ok(export_path)
+                })
+                .collect::<anyhow::Result<Vec<PathBuf>>>()?
+        }
+    };
+    Ok(export_paths)
+}
+
+/// 将`book`以`format`格式导出到`path`
+pub fn export_book(book: &ExportBook, format: ExportFormat, path: &Path) -> anyhow::Result<()> {
+    if book.page_count() == 0 {
+        return Err(anyhow!(messages::nothing_to_export(&book.title)));
+    }
+    // 保证输出目录存在
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context(messages::create_dir_failed(parent))?;
+    }
+    // 先写入临时文件，全部写完再重命名，避免失败时留下不完整的文件
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let result = match format {
+        ExportFormat::Epub => epub::write_epub(book, &tmp_path),
+        ExportFormat::Pdf => pdf::write_pdf(book, &tmp_path),
+    }
+    .and_then(|()| {
+        std::fs::rename(&tmp_path, path).context(messages::rename_failed(&tmp_path, path))
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// 扫描已去水印的漫画目录`manga_dir`，按自然顺序收集章节及其图片
+pub fn collect_chapters(
+    manga_dir: &Path,
+    scan_options: &ScanOptions,
+    scan_cache: &ScanCache,
+) -> anyhow::Result<Vec<ExportChapter>> {
+    let manga_tree = scanner::scan_manga_dir(manga_dir, scan_options, scan_cache)?;
+    manga_tree
+        .chapters
+        .into_iter()
+        .map(|chapter| {
+            let title = dir_name(&chapter.dir)?;
+            Ok(ExportChapter {
+                title,
+                pages: chapter.pages,
+            })
+        })
+        .collect()
+}
+
+/// 获取目录`dir`的名字
+pub fn dir_name(dir: &Path) -> anyhow::Result<String> {
+    let name = dir
+        .file_name()
+        .ok_or(anyhow!(messages::dir_name_failed(dir)))?
+        .to_str()
+        .ok_or(anyhow!(messages::dir_name_non_utf8(dir)))?
+        .to_string();
+    Ok(name)
+}
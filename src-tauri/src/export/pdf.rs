@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::codecs::jpeg::JpegDecoder;
+use image::{ColorType, ImageDecoder};
+
+use crate::export::ExportBook;
+use crate::messages;
+use crate::utils;
+
+// 固定的对象编号，页面相关的对象从`FIRST_PAGE_OBJECT_ID`开始依次编号
+const CATALOG_ID: usize = 1;
+const PAGES_ID: usize = 2;
+const INFO_ID: usize = 3;
+const FIRST_PAGE_OBJECT_ID: usize = 4;
+
+/// 可以直接嵌入PDF的图片数据，jpg图片是原始的jpg数据，其他图片是zlib压缩后的像素数据
+struct PdfImage {
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+    // 解码`data`使用的过滤器
+    filter: &'static str,
+    data: Vec<u8>,
+}
+
+/// 边写边记录每个对象偏移量的PDF写入器，对象写完就落盘，内存中只保留偏移量
+struct PdfWriter<W: Write> {
+    writer: W,
+    // 已经写入的字节数
+    position: usize,
+    // 对象编号 - 1 => 对象在文件中的偏移量
+    offsets: Vec<usize>,
+}
+
+impl<W: Write> PdfWriter<W> {
+    fn new(writer: W) -> std::io::Result<Self> {
+        let mut pdf_writer = Self {
+            writer,
+            position: 0,
+            offsets: vec![],
+        };
+        // 第二行的二进制注释用于告诉其他程序这是二进制文件
+        pdf_writer.write_bytes(b"%PDF-1.5\n%\xE2\xE3\xCF\xD3\n")?;
+        Ok(pdf_writer)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.position += bytes.len();
+        Ok(())
+    }
+
+    fn begin_object(&mut self, id: usize) -> std::io::Result<()> {
+        if self.offsets.len() < id {
+            self.offsets.resize(id, 0);
+        }
+        self.offsets[id - 1] = self.position;
+        self.write_bytes(format!("{id} 0 obj\n").as_bytes())
+    }
+
+    fn write_object(&mut self, id: usize, object: &str) -> std::io::Result<()> {
+        self.begin_object(id)?;
+        self.write_bytes(format!("{object}\nendobj\n").as_bytes())
+    }
+
+    fn write_stream(&mut self, id: usize, dictionary: &str, data: &[u8]) -> std::io::Result<()> {
+        self.begin_object(id)?;
+        let len = data.len();
+        self.write_bytes(format!("<< {dictionary} /Length {len} >>\nstream\n").as_bytes())?;
+        self.write_bytes(data)?;
+        self.write_bytes(b"\nendstream\nendobj\n")
+    }
+
+    /// 写入交叉引用表和trailer，返回底层的writer
+    fn finish(mut self) -> std::io::Result<W> {
+        let xref_offset = self.position;
+        let size = self.offsets.len() + 1;
+        let mut xref = format!("xref\n0 {size}\n0000000000 65535 f \n");
+        for offset in &self.offsets {
+            xref.push_str(&format!("{offset:010} 00000 n \n"));
+        }
+        xref.push_str(&format!(
+            "trailer\n<< /Size {size} /Root {CATALOG_ID} 0 R /Info {INFO_ID} 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n"
+        ));
+        self.write_bytes(xref.as_bytes())?;
+        Ok(self.writer)
+    }
+}
+
+/// 将`book`写入为只包含图片的PDF文件，每张图片占一页，章节名作为书签
+///
+/// 每读取一张图片就立即写入文件，内存中只保留当前页面的图片
+pub fn write_pdf(book: &ExportBook, path: &Path) -> anyhow::Result<()> {
+    let file = File::create(path).context(messages::create_file_failed(path))?;
+    let mut pdf = PdfWriter::new(BufWriter::new(file)).context(messages::write_pdf_failed(path))?;
+    let mut next_id = FIRST_PAGE_OBJECT_ID;
+    let mut page_ids = vec![];
+    // (章节名, 章节第一页的id)
+    let mut toc = vec![];
+    for chapter in &book.chapters {
+        for (i, img_path) in chapter.pages.iter().enumerate() {
+            let image = load_pdf_image(img_path)?;
+            let page_id = write_image_page(&mut pdf, next_id, &image)
+                .context(messages::write_pdf_failed(path))?;
+            next_id = page_id + 1;
+            if i == 0 {
+                toc.push((chapter.title.as_str(), page_id));
+            }
+            page_ids.push(page_id);
+        }
+    }
+
+    write_document_objects(&mut pdf, &book.title, &page_ids, &toc, next_id)
+        .context(messages::write_pdf_failed(path))?;
+    pdf.finish()
+        .and_then(|mut writer| writer.flush())
+        .context(messages::write_pdf_failed(path))?;
+    Ok(())
+}
+
+/// 从`first_id`开始写入图片、内容流和页面三个对象，页面尺寸与图片尺寸一致，返回页面的id
+fn write_image_page<W: Write>(
+    pdf: &mut PdfWriter<W>,
+    first_id: usize,
+    image: &PdfImage,
+) -> std::io::Result<usize> {
+    let (image_id, content_id, page_id) = (first_id, first_id + 1, first_id + 2);
+    let (width, height) = (image.width, image.height);
+    let (color_space, filter) = (image.color_space, image.filter);
+    pdf.write_stream(
+        image_id,
+        &format!("/Type /XObject /Subtype /Image /Width {width} /Height {height} /ColorSpace /{color_space} /BitsPerComponent 8 /Filter /{filter}"),
+        &image.data,
+    )?;
+    let content = format!("q {width} 0 0 {height} 0 0 cm /Im0 Do Q");
+    pdf.write_stream(content_id, "", content.as_bytes())?;
+    pdf.write_object(
+        page_id,
+        &format!("<< /Type /Page /Parent {PAGES_ID} 0 R /MediaBox [0 0 {width} {height}] /Contents {content_id} 0 R /Resources << /XObject << /Im0 {image_id} 0 R >> >> >>"),
+    )?;
+    Ok(page_id)
+}
+
+/// 所有页面写完后再写入页面树、书签、目录和文档信息
+fn write_document_objects<W: Write>(
+    pdf: &mut PdfWriter<W>,
+    title: &str,
+    page_ids: &[usize],
+    toc: &[(&str, usize)],
+    next_id: usize,
+) -> std::io::Result<()> {
+    let kids = page_ids
+        .iter()
+        .map(|id| format!("{id} 0 R"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let count = page_ids.len();
+    pdf.write_object(
+        PAGES_ID,
+        &format!("<< /Type /Pages /Kids [{kids}] /Count {count} >>"),
+    )?;
+
+    let mut catalog = format!("<< /Type /Catalog /Pages {PAGES_ID} 0 R");
+    if let Some(outlines_id) = write_outlines(pdf, toc, next_id)? {
+        catalog.push_str(&format!(
+            " /Outlines {outlines_id} 0 R /PageMode /UseOutlines"
+        ));
+    }
+    catalog.push_str(" >>");
+    pdf.write_object(CATALOG_ID, &catalog)?;
+    pdf.write_object(INFO_ID, &format!("<< /Title {} >>", text_string(title)))
+}
+
+/// 从`first_id`开始为每个章节写入一个书签，返回书签根节点的id
+fn write_outlines<W: Write>(
+    pdf: &mut PdfWriter<W>,
+    toc: &[(&str, usize)],
+    first_id: usize,
+) -> std::io::Result<Option<usize>> {
+    if toc.is_empty() {
+        return Ok(None);
+    }
+    let outlines_id = first_id;
+    let item_ids: Vec<usize> = (1..=toc.len()).map(|i| first_id + i).collect();
+    for (i, (title, page_id)) in toc.iter().enumerate() {
+        let mut item = format!(
+            "<< /Title {} /Parent {outlines_id} 0 R /Dest [{page_id} 0 R /Fit]",
+            text_string(title)
+        );
+        if i > 0 {
+            item.push_str(&format!(" /Prev {} 0 R", item_ids[i - 1]));
+        }
+        if let Some(next_id) = item_ids.get(i + 1) {
+            item.push_str(&format!(" /Next {next_id} 0 R"));
+        }
+        item.push_str(" >>");
+        pdf.write_object(item_ids[i], &item)?;
+    }
+    let (first, last, count) = (item_ids[0], item_ids[item_ids.len() - 1], item_ids.len());
+    pdf.write_object(
+        outlines_id,
+        &format!("<< /Type /Outlines /First {first} 0 R /Last {last} 0 R /Count {count} >>"),
+    )?;
+    Ok(Some(outlines_id))
+}
+
+/// 将`text`编码为带BOM的UTF-16BE十六进制字符串，可以表示任意字符
+fn text_string(text: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        hex.push_str(&format!("{unit:04X}"));
+    }
+    hex.push('>');
+    hex
+}
+
+/// 读取图片`img_path`，jpg图片直接嵌入，其他格式的图片解码后无损压缩嵌入
+fn load_pdf_image(img_path: &Path) -> anyhow::Result<PdfImage> {
+    let data = std::fs::read(img_path).context(messages::read_image_failed(img_path))?;
+    if image::guess_format(&data).ok() == Some(image::ImageFormat::Jpeg) {
+        let decoder = JpegDecoder::new(Cursor::new(&data))
+            .context(messages::decode_image_failed(img_path))?;
+        let (width, height) = decoder.dimensions();
+        let color_space = match decoder.color_type() {
+            ColorType::L8 => Some("DeviceGray"),
+            ColorType::Rgb8 => Some("DeviceRGB"),
+            _ => None,
+        };
+        if let Some(color_space) = color_space {
+            return Ok(PdfImage {
+                width,
+                height,
+                color_space,
+                filter: "DCTDecode",
+                data,
+            });
+        }
+    }
+
+    let img = utils::decode_image_bytes(&data, img_path)?;
+    let (width, height) = (img.width(), img.height());
+    // 灰度图保持灰度，其他图片转换为RGB，透明通道会被丢弃
+    let (color_space, pixels) = if img.color().has_color() {
+        ("DeviceRGB", img.into_rgb8().into_raw())
+    } else {
+        ("DeviceGray", img.into_luma8().into_raw())
+    };
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    encoder
+        .write_all(&pixels)
+        .context(messages::encode_image_failed(img_path))?;
+    let data = encoder
+        .finish()
+        .context(messages::encode_image_failed(img_path))?;
+    Ok(PdfImage {
+        width,
+        height,
+        color_space,
+        filter: "FlateDecode",
+        data,
+    })
+}
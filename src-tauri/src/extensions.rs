@@ -1,3 +1,5 @@
+use crate::types::SourceFormat;
+
 pub trait AnyhowErrorToStringChain {
     /// 将 `anyhow::Error` 转换为chain格式  
     /// # Example
@@ -20,23 +22,13 @@ impl AnyhowErrorToStringChain for anyhow::Error {
 }
 
 pub trait PathIsImage {
-    /// 判断路径是否为图片文件  
-    /// # Example
-    /// ```
-    /// use std::path::Path;
-    /// use tauri_manga::extensions::PathIsImage;
-    ///
-    /// let path = Path::new("test.jpg");
-    /// assert_eq!(path.is_image(), true);
-    /// ```
+    /// 根据文件开头的magic bytes判断路径是否为支持的图片文件，与后缀无关  
+    /// 所以后缀错误的图片(例如后缀为jpg的png图片)和没有后缀的图片也能被识别
     fn is_image(&self) -> bool;
 }
 
 impl PathIsImage for std::path::Path {
     fn is_image(&self) -> bool {
-        self.extension()
-            .and_then(|ext| ext.to_str())
-            .map(str::to_lowercase)
-            .is_some_and(|ext| matches!(ext.as_str(), "jpg" | "jpeg" | "png" | "webp" | "gif"))
+        SourceFormat::sniff(self).is_ok_and(|format| format.is_some())
     }
 }
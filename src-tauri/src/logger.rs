@@ -0,0 +1,198 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use parking_lot::{Mutex, MutexGuard};
+use serde::Deserialize;
+use tauri::AppHandle;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::messages;
+use crate::paths;
+use crate::types::LogEntry;
+
+const LOG_FILE_NAME: &str = "app.log";
+/// 单个日志文件的最大字节数，超过后轮转
+const MAX_LOG_BYTES: u64 = 5 * 1024 * 1024;
+/// 除了正在写入的日志文件，最多保留多少个轮转后的日志文件
+const MAX_ROTATED_FILES: usize = 4;
+
+/// 日志目录，位于数据目录中
+pub fn log_dir(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    let data_dir = paths::data_dir(app)?;
+    Ok(data_dir.join("logs"))
+}
+
+/// 正在写入的日志文件的路径
+pub fn log_path(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    Ok(log_dir(app)?.join(LOG_FILE_NAME))
+}
+
+/// 初始化日志，每条日志以一行JSON的格式写入日志目录，debug构建时同时输出到终端
+pub fn init(app: &AppHandle) -> anyhow::Result<()> {
+    let log_file = LogFile::open(&log_dir(app)?)?;
+    let file_layer = tracing_subscriber::fmt::layer()
+        .json()
+        .with_timer(LocalTime)
+        .with_writer(log_file);
+    let stdout_layer =
+        cfg!(debug_assertions).then(|| tracing_subscriber::fmt::layer().with_timer(LocalTime));
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(file_layer)
+        .with(stdout_layer)
+        .try_init()
+        .context(messages::init_log_failed())?;
+    Ok(())
+}
+
+/// 最近的`count`条日志，按时间从旧到新排列，正在写入的日志文件中不够时会继续读取上一个日志文件
+pub fn read_recent(app: &AppHandle, count: usize) -> anyhow::Result<Vec<LogEntry>> {
+    let log_dir = log_dir(app)?;
+    let mut lines = VecDeque::with_capacity(count + 1);
+    for log_path in [rotated_path(&log_dir, 1), log_dir.join(LOG_FILE_NAME)] {
+        if !log_path.exists() {
+            continue;
+        }
+        let file = File::open(&log_path).context(messages::open_log_failed(&log_path))?;
+        for line in BufReader::new(file).lines() {
+            let line = line.context(messages::read_log_failed(&log_path))?;
+            lines.push_back(line);
+            if lines.len() > count {
+                lines.pop_front();
+            }
+        }
+    }
+    // 写入时崩溃可能留下不完整的行，跳过无法解析的行
+    let entries = lines.iter().filter_map(|line| parse_entry(line)).collect();
+    Ok(entries)
+}
+
+/// `tracing_subscriber`输出的一行JSON日志
+#[derive(Deserialize)]
+struct RawEntry {
+    timestamp: String,
+    level: String,
+    target: String,
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+fn parse_entry(line: &str) -> Option<LogEntry> {
+    let raw: RawEntry = serde_json::from_str(line).ok()?;
+    let mut message = String::new();
+    let mut fields = BTreeMap::new();
+    for (name, value) in raw.fields {
+        let value = match value {
+            serde_json::Value::String(value) => value,
+            value => value.to_string(),
+        };
+        if name == "message" {
+            message = value;
+        } else {
+            fields.insert(name, value);
+        }
+    }
+    Some(LogEntry {
+        timestamp: raw.timestamp,
+        level: raw.level,
+        target: raw.target,
+        message,
+        fields,
+    })
+}
+
+/// 日志中使用本地时间，方便和用户描述的时间对照
+struct LocalTime;
+
+impl FormatTime for LocalTime {
+    fn format_time(&self, w: &mut Writer<'_>) -> std::fmt::Result {
+        write!(w, "{}", chrono::Local::now().to_rfc3339())
+    }
+}
+
+/// 按大小轮转的日志文件，总是写入`app.log`，超过大小后依次重命名为`app.log.1`、`app.log.2`...，编号越大越旧
+struct LogFile {
+    inner: Mutex<RotatingFile>,
+}
+
+struct RotatingFile {
+    dir: PathBuf,
+    file: File,
+    // 当前日志文件的字节数
+    len: u64,
+}
+
+impl LogFile {
+    fn open(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir).context(messages::create_dir_failed(dir))?;
+        let log_path = dir.join(LOG_FILE_NAME);
+        let file = open_append(&log_path).context(messages::open_log_failed(&log_path))?;
+        let len = file.metadata().map_or(0, |metadata| metadata.len());
+        let inner = Mutex::new(RotatingFile {
+            dir: dir.to_path_buf(),
+            file,
+            len,
+        });
+        Ok(Self { inner })
+    }
+}
+
+impl RotatingFile {
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        // 最旧的日志文件会被覆盖
+        for index in (1..MAX_ROTATED_FILES).rev() {
+            let from = rotated_path(&self.dir, index);
+            if from.exists() {
+                std::fs::rename(from, rotated_path(&self.dir, index + 1))?;
+            }
+        }
+        let log_path = self.dir.join(LOG_FILE_NAME);
+        std::fs::rename(&log_path, rotated_path(&self.dir, 1))?;
+        self.file = open_append(&log_path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = LogFileWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogFileWriter(self.inner.lock())
+    }
+}
+
+/// 持有锁期间写入一条完整的日志，保证轮转只发生在两条日志之间
+struct LogFileWriter<'a>(MutexGuard<'a, RotatingFile>);
+
+impl Write for LogFileWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let rotating = &mut self.0;
+        if rotating.len > 0 && rotating.len + buf.len() as u64 > MAX_LOG_BYTES {
+            rotating.rotate()?;
+        }
+        let written = rotating.file.write(buf)?;
+        rotating.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn rotated_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("{LOG_FILE_NAME}.{index}"))
+}
@@ -4,18 +4,40 @@
 
 use parking_lot::RwLock;
 use tauri::{Context, Manager, Wry};
+use tauri_plugin_fs::FsExt;
 
 use crate::commands::prelude::*;
 use crate::config::Config;
 use crate::events::prelude::*;
+use crate::extensions::AnyhowErrorToStringChain;
+use crate::queue::JobQueue;
+use crate::remover::ProcessingResources;
+use crate::report::JobHistory;
+use crate::scan_cache::ScanCache;
+use crate::watcher::MangaWatcher;
 
+mod backup;
+mod comic_info;
 mod commands;
 mod config;
 mod errors;
 mod events;
+mod export;
 mod extensions;
+mod logger;
+mod manifest;
+mod messages;
+mod natural_sort;
+mod output_template;
+mod paths;
+mod queue;
+mod remover;
+mod report;
+mod scan_cache;
+mod scanner;
 mod types;
 mod utils;
+mod watcher;
 
 fn generate_context() -> Context<Wry> {
     tauri::generate_context!()
@@ -34,12 +56,41 @@ fn main() {
             get_background_dir_abs_path,
             get_config,
             save_config,
+            export_manga,
+            scan_manga_dir,
+            clear_scan_cache,
+            stream_image_infos,
+            restore_originals,
+            plan_remove_watermark,
+            enqueue_job,
+            get_queue,
+            move_job,
+            remove_job,
+            start_queue,
+            pause_queue,
+            clear_finished_jobs,
+            retry_job,
+            get_watch_state,
+            add_watch_dir,
+            remove_watch_dir,
+            set_watch_enabled,
+            save_job_report,
+            retry_failed,
+            read_log,
+            open_log_dir,
         ])
         .events(tauri_specta::collect_events![
             RemoveWatermarkStartEvent,
             RemoveWatermarkSuccessEvent,
             RemoveWatermarkErrorEvent,
             RemoveWatermarkEndEvent,
+            RemoveWatermarkProgressEvent,
+            RemoveWatermarkSummaryEvent,
+            ScanBatchEvent,
+            ScanEndEvent,
+            QueueUpdateEvent,
+            WatchErrorEvent,
+            PipelineStatsEvent,
         ]);
     // 只有在debug模式下才会生成bindings.ts
     #[cfg(debug_assertions)]
@@ -60,8 +111,31 @@ fn main() {
         .invoke_handler(builder.invoke_handler())
         .setup(move |app| {
             builder.mount_events(app);
+            logger::init(app.handle())?;
+            // 迁移失败时仍然可以使用新的位置，只是旧的设置和背景水印图需要重新设置
+            if let Err(err) = paths::migrate(app.handle()) {
+                let err_msg = err.to_string_chain();
+                tracing::warn!(err_msg, "{}", messages::log_migration_failed());
+            }
+            // 前端需要读取数据目录中的背景水印图，数据目录在便携模式下是资源目录，否则是系统的数据目录，
+            // 把实际使用的数据目录加入fs的scope，不依赖capabilities中固定的路径
+            app.fs_scope()
+                .allow_directory(paths::data_dir(app.handle())?, true);
             let config = RwLock::new(Config::new(app.handle())?);
             app.manage(config);
+            let scan_cache = ScanCache::load(app.handle())?;
+            app.manage(scan_cache);
+            app.manage(ProcessingResources::default());
+            app.manage(JobHistory::default());
+            let queue = JobQueue::load(app.handle())?;
+            app.manage(queue);
+            let watcher = MangaWatcher::load(app.handle())?;
+            let watch_enabled = watcher.state().enabled;
+            app.manage(watcher);
+            if watch_enabled {
+                // 监视的目录可能已经不存在，不应该因此无法启动
+                let _ = app.state::<MangaWatcher>().start(app.handle());
+            }
             Ok(())
         })
         .run(generate_context())
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+
+use crate::messages;
+
+/// 清单文件的文件名，原地处理时位于漫画目录中
+const MANIFEST_FILE_NAME: &str = ".watermark-remover-manifest.json";
+/// 输出到其他目录时，每部漫画的清单都保存在输出目录的这个子目录中，文件名是漫画名
+const MANIFEST_DIR_NAME: &str = ".watermark-remover-manifests";
+/// 保存清单时加锁，多个任务同时保存同一个清单时依次合并，不会覆盖对方的记录
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 一张原图片上次处理时的信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    // 根据输出目录结构得到的输出路径
+    pub target_path: PathBuf,
+    // 实际写入的输出路径，冲突策略为重命名时可能与target_path不同
+    pub output_path: PathBuf,
+    // 原图片的大小和修改时间，都没变时不需要重新计算哈希
+    pub size: u64,
+    pub modified_secs: u64,
+    pub modified_nanos: u32,
+    // 原图片内容的blake3哈希
+    pub content_hash: String,
+    // 处理时使用的设置，如`Jpeg optimize=false`，直接复制时为`copy`
+    pub settings: String,
+    // 处理时使用的背景水印图的版本，直接复制时为`None`
+    pub background_version: Option<String>,
+}
+
+/// 输出目录中记录每张原图片处理情况的清单，用于增量处理
+pub struct Manifest {
+    path: PathBuf,
+    // 原图片路径 => 处理信息
+    entries: RwLock<HashMap<PathBuf, ManifestEntry>>,
+}
+
+impl Manifest {
+    /// 读取漫画目录`manga_dir`的清单，清单不存在或损坏时返回空清单
+    ///
+    /// 每部漫画有自己的清单，原地处理时保存在漫画目录中，否则保存在输出目录`output_dir`中；
+    /// 以前的版本所有漫画共用输出目录根目录中的一个清单，新的清单不存在时从中取出属于这部漫画的记录
+    pub fn load(manga_dir: &Path, output_dir: &Path, in_place: bool) -> Self {
+        let path = if in_place {
+            manga_dir.join(MANIFEST_FILE_NAME)
+        } else {
+            // 漫画名中可能有`.`，不能使用with_extension
+            let manga_name = manga_dir.file_name().unwrap_or_default().to_string_lossy();
+            output_dir
+                .join(MANIFEST_DIR_NAME)
+                .join(format!("{manga_name}.json"))
+        };
+        let entries = read_entries(&path).unwrap_or_else(|| {
+            let mut entries =
+                read_entries(&output_dir.join(MANIFEST_FILE_NAME)).unwrap_or_default();
+            entries.retain(|img_path, entry| {
+                img_path.starts_with(manga_dir) || entry.target_path.starts_with(manga_dir)
+            });
+            entries
+        });
+        Self {
+            path,
+            entries: RwLock::new(entries),
+        }
+    }
+
+    /// 将清单与磁盘上的清单合并后写入，先写入临时文件再重命名，中途失败不会留下不完整的清单
+    ///
+    /// 同一部漫画可能同时被多个任务处理(例如监视和手动去水印)，合并后不会丢失其他任务的记录，相同的图片以这次的记录为准
+    pub fn save(&self) -> anyhow::Result<()> {
+        let _save_guard = SAVE_LOCK.lock();
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(messages::create_dir_failed(parent))?;
+        }
+        let mut entries = read_entries(&self.path).unwrap_or_default();
+        entries.extend(
+            self.entries
+                .read()
+                .iter()
+                .map(|(img_path, entry)| (img_path.clone(), entry.clone())),
+        );
+        let manifest_string = serde_json::to_string_pretty(&entries)?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, manifest_string)
+            .context(messages::save_manifest_failed(&tmp_path))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .context(messages::rename_failed(&tmp_path, &self.path))?;
+        Ok(())
+    }
+
+    /// 如果原图片`img_path`的内容、输出路径和设置都与上次处理时一致，则返回上次实际写入的输出路径
+    ///
+    /// 大小和修改时间都没变时直接认为内容没变，否则计算哈希来比较
+    pub fn unchanged_output(
+        &self,
+        img_path: &Path,
+        stamp: &SourceStamp,
+        target_path: &Path,
+        settings: &str,
+        background_version: Option<&str>,
+    ) -> anyhow::Result<Option<PathBuf>> {
+        let Some(entry) = self.entries.read().get(img_path).cloned() else {
+            return Ok(None);
+        };
+        let same_output = entry.target_path == target_path && entry.output_path.exists();
+        let same_settings =
+            entry.settings == settings && entry.background_version.as_deref() == background_version;
+        if !same_output || !same_settings {
+            return Ok(None);
+        }
+        if stamp.matches(&entry) {
+            return Ok(Some(entry.output_path));
+        }
+        // 修改时间变了但内容没变(比如文件被touch过)，更新记录的修改时间，下次就不用再计算哈希
+        if content_hash(img_path)? != entry.content_hash {
+            return Ok(None);
+        }
+        let output_path = entry.output_path.clone();
+        let entry = ManifestEntry {
+            size: stamp.size,
+            modified_secs: stamp.modified_secs,
+            modified_nanos: stamp.modified_nanos,
+            ..entry
+        };
+        self.entries.write().insert(img_path.to_path_buf(), entry);
+        Ok(Some(output_path))
+    }
+
+    /// 记录原图片`img_path`的处理信息
+    pub fn record(&self, img_path: PathBuf, entry: ManifestEntry) {
+        self.entries.write().insert(img_path, entry);
+    }
+}
+
+/// 读取清单文件`path`，不存在或损坏时返回`None`
+fn read_entries(path: &Path) -> Option<HashMap<PathBuf, ManifestEntry>> {
+    let manifest_string = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&manifest_string).ok()
+}
+
+/// 原图片的大小和修改时间
+pub struct SourceStamp {
+    pub size: u64,
+    pub modified_secs: u64,
+    pub modified_nanos: u32,
+}
+
+impl SourceStamp {
+    pub fn read(img_path: &Path) -> anyhow::Result<Self> {
+        let metadata = std::fs::metadata(img_path).context(messages::metadata_failed(img_path))?;
+        let modified = metadata
+            .modified()
+            .context(messages::modified_time_failed(img_path))?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(Self {
+            size: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+
+    fn matches(&self, entry: &ManifestEntry) -> bool {
+        self.size == entry.size
+            && self.modified_secs == entry.modified_secs
+            && self.modified_nanos == entry.modified_nanos
+    }
+}
+
+/// 计算文件`path`内容的blake3哈希
+pub fn content_hash(path: &Path) -> anyhow::Result<String> {
+    let mut file = File::open(path).context(messages::open_file_failed(path))?;
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut file, &mut hasher).context(messages::read_file_failed(path))?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// 计算已经读入内存的文件内容`data`的哈希，与`content_hash`的结果相同
+pub fn content_hash_of(data: &[u8]) -> String {
+    blake3::hash(data).to_hex().to_string()
+}
+
+/// 计算背景水印图的版本，背景水印图重新生成后版本会改变
+pub fn background_version(black: &[u8], white: &[u8]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(black);
+    hasher.update(white);
+    hasher.finalize().to_hex().to_string()
+}
@@ -0,0 +1,524 @@
+use std::path::Path;
+
+use parking_lot::RwLock;
+
+use crate::types::Locale;
+
+/// 后端消息使用的语言，启动时和保存配置时根据`Config`设置
+static LOCALE: RwLock<Locale> = RwLock::new(Locale::ZhCn);
+
+pub fn set_locale(locale: Locale) {
+    *LOCALE.write() = locale;
+}
+
+pub fn locale() -> Locale {
+    *LOCALE.read()
+}
+
+/// 为每条消息生成一个函数，根据当前的语言返回对应的文本，文本中可以直接使用参数名作为占位符
+macro_rules! messages {
+    ($($(#[$attr:meta])* $name:ident($($arg:ident: $ty:ty),* $(,)?) {
+        zh_cn: $zh_cn:literal,
+        en: $en:literal $(,)?
+    })*) => {
+        $(
+            $(#[$attr])*
+            pub fn $name($($arg: $ty),*) -> String {
+                match locale() {
+                    Locale::ZhCn => format!($zh_cn),
+                    Locale::En => format!($en),
+                }
+            }
+        )*
+    };
+}
+
+// 文件和目录
+messages! {
+    create_dir_failed(path: &Path) {
+        zh_cn: "创建目录 {path:?} 失败",
+        en: "Failed to create directory {path:?}",
+    }
+    create_file_failed(path: &Path) {
+        zh_cn: "创建文件 {path:?} 失败",
+        en: "Failed to create file {path:?}",
+    }
+    open_file_failed(path: &Path) {
+        zh_cn: "打开文件 {path:?} 失败",
+        en: "Failed to open file {path:?}",
+    }
+    read_file_failed(path: &Path) {
+        zh_cn: "读取文件 {path:?} 失败",
+        en: "Failed to read file {path:?}",
+    }
+    write_file_failed(path: &Path) {
+        zh_cn: "写入 {path:?} 失败",
+        en: "Failed to write {path:?}",
+    }
+    delete_failed(path: &Path) {
+        zh_cn: "删除 {path:?} 失败",
+        en: "Failed to delete {path:?}",
+    }
+    copy_failed(from: &Path, to: &Path) {
+        zh_cn: "复制 {from:?} 到 {to:?} 失败",
+        en: "Failed to copy {from:?} to {to:?}",
+    }
+    rename_failed(from: &Path, to: &Path) {
+        zh_cn: "将 {from:?} 重命名为 {to:?} 失败",
+        en: "Failed to rename {from:?} to {to:?}",
+    }
+    metadata_failed(path: &Path) {
+        zh_cn: "获取文件 {path:?} 的元数据失败",
+        en: "Failed to get the metadata of {path:?}",
+    }
+    modified_time_failed(path: &Path) {
+        zh_cn: "获取文件 {path:?} 的修改时间失败",
+        en: "Failed to get the modification time of {path:?}",
+    }
+    not_parent_dir(parent: &Path, path: &Path) {
+        zh_cn: "{parent:?} 不是 {path:?} 的父目录",
+        en: "{parent:?} is not a parent directory of {path:?}",
+    }
+    non_utf8_path(path: &Path) {
+        zh_cn: "{path:?} 包含非UTF-8字符",
+        en: "{path:?} contains non-UTF-8 characters",
+    }
+    dir_name_failed(dir: &Path) {
+        zh_cn: "获取目录 {dir:?} 的名字失败",
+        en: "Failed to get the name of directory {dir:?}",
+    }
+    dir_name_non_utf8(dir: &Path) {
+        zh_cn: "目录名 {dir:?} 包含非UTF-8字符",
+        en: "Name of directory {dir:?} contains non-UTF-8 characters",
+    }
+    file_name_failed(path: &Path) {
+        zh_cn: "获取 {path:?} 的文件名失败",
+        en: "Failed to get the file name of {path:?}",
+    }
+    read_dir_failed(dir: &Path) {
+        zh_cn: "读取目录 {dir:?} 失败",
+        en: "Failed to read directory {dir:?}",
+    }
+    new_file_name_failed(path: &Path) {
+        zh_cn: "为 {path:?} 生成新的文件名失败",
+        en: "Failed to generate a new file name for {path:?}",
+    }
+}
+
+// 图片
+messages! {
+    open_image_failed(path: &Path) {
+        zh_cn: "打开图片 {path:?} 失败",
+        en: "Failed to open image {path:?}",
+    }
+    read_image_failed(path: &Path) {
+        zh_cn: "读取图片 {path:?} 失败",
+        en: "Failed to read image {path:?}",
+    }
+    decode_image_failed(path: &Path) {
+        zh_cn: "解码图片 {path:?} 失败",
+        en: "Failed to decode image {path:?}",
+    }
+    #[cfg(feature = "jxl")]
+    read_jxl_header_failed(path: &Path) {
+        zh_cn: "读取JPEG XL图片 {path:?} 的头部失败",
+        en: "Failed to read the header of JPEG XL image {path:?}",
+    }
+    image_dimensions_failed(path: &Path) {
+        zh_cn: "获取图片 {path:?} 的尺寸失败",
+        en: "Failed to get the dimensions of image {path:?}",
+    }
+    unsupported_image_format(path: &Path) {
+        zh_cn: "{path:?} 不是支持的图片格式",
+        en: "{path:?} is not a supported image format",
+    }
+    convert_to_png_failed(path: &Path) {
+        zh_cn: "将图片 {path:?} 转换为png失败",
+        en: "Failed to convert image {path:?} to png",
+    }
+    encode_image_failed(path: &Path) {
+        zh_cn: "编码图片 {path:?} 失败",
+        en: "Failed to encode image {path:?}",
+    }
+    save_image_failed(path: &Path) {
+        zh_cn: "保存图片 {path:?} 失败",
+        en: "Failed to save image {path:?}",
+    }
+    encode_luma8_failed() {
+        zh_cn: "编码luma8图片失败",
+        en: "Failed to encode luma8 image",
+    }
+    encode_rgb_failed() {
+        zh_cn: "编码rgb图片失败",
+        en: "Failed to encode rgb image",
+    }
+    image_name_failed(path: &Path) {
+        zh_cn: "获取图片 {path:?} 的文件名失败",
+        en: "Failed to get the file name of image {path:?}",
+    }
+}
+
+// 漫画目录
+messages! {
+    manga_name_failed(manga_dir: &Path) {
+        zh_cn: "获取漫画目录 {manga_dir:?} 的名字失败",
+        en: "Failed to get the name of manga directory {manga_dir:?}",
+    }
+    manga_parent_missing(manga_dir: &Path) {
+        zh_cn: "漫画目录 {manga_dir:?} 的父目录不存在",
+        en: "Parent directory of manga directory {manga_dir:?} does not exist",
+    }
+    manga_dir_non_utf8(manga_dir: &Path) {
+        zh_cn: "漫画目录 {manga_dir:?} 包含非UTF-8字符",
+        en: "Manga directory {manga_dir:?} contains non-UTF-8 characters",
+    }
+    manga_dir_not_found(manga_dir: &Path) {
+        zh_cn: "漫画目录 {manga_dir:?} 不存在",
+        en: "Manga directory {manga_dir:?} does not exist",
+    }
+    manga_dir_no_images(manga_dir: &Path) {
+        zh_cn: "漫画目录 {manga_dir:?} 中没有图片",
+        en: "Manga directory {manga_dir:?} contains no images",
+    }
+    chapter_name_failed(dir: &Path) {
+        zh_cn: "获取章节目录 {dir:?} 的名字失败",
+        en: "Failed to get the name of chapter directory {dir:?}",
+    }
+}
+
+// 备份
+messages! {
+    no_backup_for_image(img_path: &Path) {
+        zh_cn: "图片 {img_path:?} 没有备份",
+        en: "Image {img_path:?} has no backup",
+    }
+    nothing_to_restore(dir: &Path) {
+        zh_cn: "{dir:?} 中没有可以恢复的原图片",
+        en: "{dir:?} has no original images to restore",
+    }
+    no_original_backup(manga_dir: &Path) {
+        zh_cn: "漫画目录 {manga_dir:?} 没有原图备份",
+        en: "Manga directory {manga_dir:?} has no backup of the original images",
+    }
+    save_backup_index_failed(path: &Path) {
+        zh_cn: "保存备份索引 {path:?} 失败",
+        en: "Failed to save backup index {path:?}",
+    }
+    delete_backup_dir_failed(path: &Path) {
+        zh_cn: "删除备份目录 {path:?} 失败",
+        en: "Failed to delete backup directory {path:?}",
+    }
+    delete_backup_archive_failed(path: &Path) {
+        zh_cn: "删除备份压缩包 {path:?} 失败",
+        en: "Failed to delete backup archive {path:?}",
+    }
+    open_backup_archive_failed(path: &Path) {
+        zh_cn: "打开备份压缩包 {path:?} 失败",
+        en: "Failed to open backup archive {path:?}",
+    }
+    read_backup_archive_failed(path: &Path) {
+        zh_cn: "读取备份压缩包 {path:?} 失败",
+        en: "Failed to read backup archive {path:?}",
+    }
+    write_backup_archive_failed(path: &Path) {
+        zh_cn: "写入备份压缩包 {path:?} 失败",
+        en: "Failed to write backup archive {path:?}",
+    }
+    extract_failed(path: &Path) {
+        zh_cn: "解压 {path:?} 失败",
+        en: "Failed to extract {path:?}",
+    }
+    compress_failed(path: &Path) {
+        zh_cn: "压缩 {path:?} 失败",
+        en: "Failed to compress {path:?}",
+    }
+}
+
+// 背景水印图
+messages! {
+    background_not_found(width: u32, height: u32) {
+        zh_cn: "找不到尺寸为({width}x{height})的背景水印图\n",
+        en: "No background watermark image of size ({width}x{height}) was found\n",
+    }
+    background_pair_incomplete(width: u32, height: u32) {
+        zh_cn: "只找到一张尺寸为({width}x{height})的背景水印图\n",
+        en: "Only one background watermark image of size ({width}x{height}) was found\n",
+    }
+    background_dir_missing(width: u32, height: u32, dir: &Path) {
+        zh_cn: "尺寸为 ({width}x{height}) 的背景水印图 {dir:?} 不存在",
+        en: "Background watermark images of size ({width}x{height}) {dir:?} do not exist",
+    }
+    background_changed(width: u32, height: u32) {
+        zh_cn: "尺寸为 ({width}x{height}) 的背景水印图在生成报告后被修改过，无法按照相同的设置重试",
+        en: "Background watermark images of size ({width}x{height}) were modified after the report was generated, cannot retry with the same settings",
+    }
+    read_black_background_failed(path: &Path) {
+        zh_cn: "读取黑色背景水印图 {path:?} 失败",
+        en: "Failed to read black background watermark image {path:?}",
+    }
+    read_white_background_failed(path: &Path) {
+        zh_cn: "读取白色背景水印图 {path:?} 失败",
+        en: "Failed to read white background watermark image {path:?}",
+    }
+    convert_black_background_failed(path: &Path) {
+        zh_cn: "黑色背景水印图 {path:?} 转换失败",
+        en: "Failed to convert black background watermark image {path:?}",
+    }
+    convert_white_background_failed(path: &Path) {
+        zh_cn: "白色背景水印图 {path:?} 转换失败",
+        en: "Failed to convert white background watermark image {path:?}",
+    }
+    background_size_mismatch(
+        black_width: u32,
+        black_height: u32,
+        white_width: u32,
+        white_height: u32,
+    ) {
+        zh_cn: "黑色背景和白色背景水印图的尺寸不一致，黑色背景水印图的尺寸是 ({black_width}x{black_height})，白色背景水印图的尺寸是 ({white_width}x{white_height})",
+        en: "Black and white background watermark images have different sizes, the black one is ({black_width}x{black_height}) and the white one is ({white_width}x{white_height})",
+    }
+}
+
+// 设置
+messages! {
+    read_config_failed(path: &Path) {
+        zh_cn: "读取配置文件 {path:?} 失败",
+        en: "Failed to read config file {path:?}",
+    }
+    save_config_failed(path: &Path) {
+        zh_cn: "保存配置文件 {path:?} 失败",
+        en: "Failed to save config file {path:?}",
+    }
+    invalid_glob(pattern: &str) {
+        zh_cn: "glob模式 {pattern:?} 无效",
+        en: "Invalid glob pattern {pattern:?}",
+    }
+    build_glob_set_failed() {
+        zh_cn: "构建glob模式集合失败",
+        en: "Failed to build the glob pattern set",
+    }
+    invalid_template(template: &str) {
+        zh_cn: "输出路径模板 {template:?} 无效",
+        en: "Invalid output path template {template:?}",
+    }
+    template_unclosed_brace(template: &str) {
+        zh_cn: "输出路径模板 {template:?} 中的 `{{` 没有对应的 `}}`",
+        en: "`{{` in output path template {template:?} has no matching `}}`",
+    }
+    template_path_outside(template: &str, path: &Path) {
+        zh_cn: "输出路径模板 {template:?} 渲染出的路径 {path:?} 不是输出目录内的相对路径",
+        en: "Path {path:?} rendered from output path template {template:?} is not a relative path inside the output directory",
+    }
+    template_unknown_var(template: &str, name: &str) {
+        zh_cn: "输出路径模板 {template:?} 中的变量 `{name}` 不存在",
+        en: "Variable `{name}` in output path template {template:?} does not exist",
+    }
+    template_invalid_width(template: &str, placeholder: &str) {
+        zh_cn: "输出路径模板 {template:?} 中 `{placeholder}` 的宽度无效",
+        en: "Invalid width of `{placeholder}` in output path template {template:?}",
+    }
+    template_no_manga_dir(template: &str) {
+        zh_cn: "输出路径模板 {template:?} 没有为每部漫画创建单独的目录，开头的目录中需要有只由 `{{manga}}` 和固定文字组成的一级，如 `{{manga}}/{{chapter}}/{{page:04}}.{{ext}}`",
+        en: "Output path template {template:?} does not give each manga its own directory, its leading directories need a level made of `{{manga}}` and fixed text only, e.g. `{{manga}}/{{chapter}}/{{page:04}}.{{ext}}`",
+    }
+    duplicate_output_path(path: &Path) {
+        zh_cn: "多张图片的输出路径都是 {path:?}，请检查输出路径模板",
+        en: "Multiple images have the same output path {path:?}, please check the output path template",
+    }
+}
+
+// 去水印、报告和导出
+messages! {
+    create_thread_pool_failed(threads: usize) {
+        zh_cn: "创建有{threads}个线程的线程池失败",
+        en: "Failed to create a thread pool with {threads} threads",
+    }
+    send_to_reader_failed() {
+        zh_cn: "发送图片到读取阶段失败，去水印已经停止",
+        en: "Failed to send images to the read stage, watermark removal has stopped",
+    }
+    pipeline_thread_panicked() {
+        zh_cn: "去水印的线程panic",
+        en: "A watermark removal thread panicked",
+    }
+    page_skipped_stages(img_path: &Path) {
+        zh_cn: "图片 {img_path:?} 没有经过前面的阶段",
+        en: "Image {img_path:?} did not go through the previous stages",
+    }
+    page_not_read(img_path: &Path) {
+        zh_cn: "图片 {img_path:?} 没有被读取",
+        en: "Image {img_path:?} was not read",
+    }
+    dir_progress_missing(dir: &Path) {
+        zh_cn: "目录 {dir:?} 的进度不存在",
+        en: "Progress of directory {dir:?} does not exist",
+    }
+    save_comic_info_failed(dir: &Path) {
+        zh_cn: "保存 {dir:?} 的ComicInfo.xml失败",
+        en: "Failed to save ComicInfo.xml of {dir:?}",
+    }
+    save_manifest_failed(path: &Path) {
+        zh_cn: "保存清单 {path:?} 失败",
+        en: "Failed to save manifest {path:?}",
+    }
+    no_failed_pages(report_id: &str) {
+        zh_cn: "报告 {report_id} 中没有处理失败的图片",
+        en: "Report {report_id} has no failed images",
+    }
+    report_not_found(report_id: &str) {
+        zh_cn: "报告 {report_id} 不存在，可能已经被更新的报告替换",
+        en: "Report {report_id} does not exist, it may have been replaced by newer reports",
+    }
+    no_report_specified() {
+        zh_cn: "没有指定要重试的报告",
+        en: "No report to retry was specified",
+    }
+    save_report_failed(path: &Path) {
+        zh_cn: "保存报告 {path:?} 失败",
+        en: "Failed to save report {path:?}",
+    }
+    read_report_failed(path: &Path) {
+        zh_cn: "读取报告 {path:?} 失败",
+        en: "Failed to read report {path:?}",
+    }
+    report_file_name(manga_name: &str, timestamp: &str, extension: &str) {
+        zh_cn: "{manga_name}-去水印报告-{timestamp}.{extension}",
+        en: "{manga_name}-watermark-report-{timestamp}.{extension}",
+    }
+    parse_report_failed(path: &Path) {
+        zh_cn: "解析报告 {path:?} 失败，只支持JSON格式的报告",
+        en: "Failed to parse report {path:?}, only JSON reports are supported",
+    }
+    nothing_to_export(title: &str) {
+        zh_cn: "{title:?} 中没有可以导出的图片",
+        en: "{title:?} has no images to export",
+    }
+    write_epub_failed(path: &Path) {
+        zh_cn: "写入EPUB文件 {path:?} 失败",
+        en: "Failed to write EPUB file {path:?}",
+    }
+    write_pdf_failed(path: &Path) {
+        zh_cn: "写入PDF文件 {path:?} 失败",
+        en: "Failed to write PDF file {path:?}",
+    }
+    epub_cover_label() {
+        zh_cn: "封面",
+        en: "Cover",
+    }
+}
+
+// 扫描缓存、队列、监视和日志
+messages! {
+    save_scan_cache_failed(path: &Path) {
+        zh_cn: "保存扫描缓存 {path:?} 失败",
+        en: "Failed to save scan cache {path:?}",
+    }
+    delete_scan_cache_failed(path: &Path) {
+        zh_cn: "删除扫描缓存 {path:?} 失败",
+        en: "Failed to delete scan cache {path:?}",
+    }
+    job_already_queued(manga_dir: &Path) {
+        zh_cn: "漫画目录 {manga_dir:?} 已经在队列中",
+        en: "Manga directory {manga_dir:?} is already in the queue",
+    }
+    manga_processing(manga_dir: &Path) {
+        zh_cn: "漫画目录 {manga_dir:?} 正在去水印，请等它完成后再试",
+        en: "Manga directory {manga_dir:?} is already being processed, try again after it finishes",
+    }
+    job_running(id: &str) {
+        zh_cn: "任务 {id} 正在执行，不能删除",
+        en: "Job {id} is running and cannot be removed",
+    }
+    job_not_failed(id: &str) {
+        zh_cn: "任务 {id} 没有失败，不需要重试",
+        en: "Job {id} has not failed and does not need to be retried",
+    }
+    job_not_found(id: &str) {
+        zh_cn: "任务 {id} 不存在",
+        en: "Job {id} does not exist",
+    }
+    save_queue_failed(path: &Path) {
+        zh_cn: "保存任务队列 {path:?} 失败",
+        en: "Failed to save job queue {path:?}",
+    }
+    watch_dir_failed(manga_dir: &Path) {
+        zh_cn: "监视漫画目录 {manga_dir:?} 失败",
+        en: "Failed to watch manga directory {manga_dir:?}",
+    }
+    create_watcher_failed() {
+        zh_cn: "创建文件监视器失败",
+        en: "Failed to create the file watcher",
+    }
+    save_watch_state_failed(path: &Path) {
+        zh_cn: "保存监视设置 {path:?} 失败",
+        en: "Failed to save watch settings {path:?}",
+    }
+    init_log_failed() {
+        zh_cn: "初始化日志失败",
+        en: "Failed to initialize logging",
+    }
+    open_log_failed(path: &Path) {
+        zh_cn: "打开日志文件 {path:?} 失败",
+        en: "Failed to open log file {path:?}",
+    }
+    read_log_failed(path: &Path) {
+        zh_cn: "读取日志文件 {path:?} 失败",
+        en: "Failed to read log file {path:?}",
+    }
+}
+
+// 日志中的事件
+messages! {
+    log_command_failed() {
+        zh_cn: "命令执行失败",
+        en: "Command failed",
+    }
+    log_scan_finished() {
+        zh_cn: "扫描漫画目录完成",
+        en: "Finished scanning manga directory",
+    }
+    log_background_generated() {
+        zh_cn: "生成背景水印图完成",
+        en: "Finished generating background watermark images",
+    }
+    log_removal_started() {
+        zh_cn: "开始去水印",
+        en: "Started removing watermarks",
+    }
+    log_removal_finished() {
+        zh_cn: "去水印完成",
+        en: "Finished removing watermarks",
+    }
+    log_exported() {
+        zh_cn: "导出完成",
+        en: "Finished exporting",
+    }
+    log_export_failed() {
+        zh_cn: "导出失败",
+        en: "Failed to export",
+    }
+    log_page_failed() {
+        zh_cn: "图片处理失败",
+        en: "Failed to process image",
+    }
+    log_job_started() {
+        zh_cn: "开始执行队列中的任务",
+        en: "Started a queued job",
+    }
+    log_job_failed() {
+        zh_cn: "队列中的任务失败",
+        en: "A queued job failed",
+    }
+    log_migrated() {
+        zh_cn: "已将资源目录中的旧文件迁移到新的位置",
+        en: "Migrated old files from the resource directory to the new location",
+    }
+    log_output_dir_migrated() {
+        zh_cn: "输出目录是只读的资源目录，已改为数据目录",
+        en: "The output directory was the read-only resource directory, changed it to the data directory",
+    }
+    log_migration_failed() {
+        zh_cn: "迁移资源目录中的旧文件失败",
+        en: "Failed to migrate old files from the resource directory",
+    }
+}
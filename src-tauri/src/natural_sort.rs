@@ -0,0 +1,347 @@
+use std::cmp::Ordering;
+use std::path::{Component, Path};
+
+/// 文件名中的一段，数字和非数字交替出现
+enum Segment<'a> {
+    // 整数部分(已去掉前导零)和小数部分，`第1.5话`中的`1.5`就是integer=`1`、fraction=`5`
+    Number { integer: String, fraction: &'a str },
+    Text(&'a str),
+}
+
+/// 以自然顺序比较`a`和`b`，数字部分按数值比较，所以`第2话`排在`第10话`前面
+///
+/// 支持半角数字、全角数字和中文数字(如`第十二话`、`第一百零五话`)
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let mut a_segments = segments(a);
+    let mut b_segments = segments(b);
+    loop {
+        let ordering = match (a_segments.next(), b_segments.next()) {
+            (Some(a_segment), Some(b_segment)) => compare_segments(&a_segment, &b_segment),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            // 自然顺序相同时(如`01`和`1`)按原始字符串比较，保证顺序是确定的
+            (None, None) => return a.cmp(b),
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+/// 以自然顺序逐级比较路径`a`和`b`的每一部分
+pub fn compare_paths(a: &Path, b: &Path) -> Ordering {
+    let a_components = a.components().map(component_str);
+    let b_components = b.components().map(component_str);
+    a_components
+        .zip(b_components)
+        .map(|(a, b)| compare(&a, &b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or_else(|| a.components().count().cmp(&b.components().count()))
+}
+
+fn compare_segments(a: &Segment, b: &Segment) -> Ordering {
+    match (a, b) {
+        (
+            Segment::Number {
+                integer: a_integer,
+                fraction: a_fraction,
+            },
+            Segment::Number {
+                integer: b_integer,
+                fraction: b_fraction,
+            },
+        ) => a_integer
+            .len()
+            .cmp(&b_integer.len())
+            .then_with(|| a_integer.cmp(b_integer))
+            .then_with(|| a_fraction.cmp(b_fraction)),
+        // 数字排在文字前面
+        (Segment::Number { .. }, Segment::Text(_)) => Ordering::Less,
+        (Segment::Text(_), Segment::Number { .. }) => Ordering::Greater,
+        // 忽略大小写
+        (Segment::Text(a), Segment::Text(b)) => a
+            .chars()
+            .flat_map(char::to_lowercase)
+            .cmp(b.chars().flat_map(char::to_lowercase)),
+    }
+}
+
+fn component_str(component: Component<'_>) -> std::borrow::Cow<'_, str> {
+    component.as_os_str().to_string_lossy()
+}
+
+/// 将`s`切分为数字和非数字交替的`Segment`
+fn segments(s: &str) -> impl Iterator<Item = Segment<'_>> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let segment = if digit_value(first).is_some() {
+            let (integer, fraction, len) = take_digits(rest);
+            rest = &rest[len..];
+            Segment::Number { integer, fraction }
+        } else if let Some((number, after)) = split_cjk_number(rest) {
+            rest = after;
+            Segment::Number {
+                integer: number.to_string(),
+                fraction: "",
+            }
+        } else {
+            let len = rest
+                .char_indices()
+                .find(|(_, c)| digit_value(*c).is_some() || is_cjk_numeral(*c))
+                .map_or(rest.len(), |(i, _)| i);
+            let text = &rest[..len];
+            rest = &rest[len..];
+            Segment::Text(text)
+        };
+        Some(segment)
+    })
+}
+
+/// 从`s`开头读取一个数字(可以是小数)，返回(去掉前导零的整数部分, 小数部分, 读取的字节数)
+fn take_digits(s: &str) -> (String, &str, usize) {
+    let integer_len = s
+        .char_indices()
+        .find(|(_, c)| digit_value(*c).is_none())
+        .map_or(s.len(), |(i, _)| i);
+    let integer: String = s[..integer_len]
+        .chars()
+        .filter_map(digit_value)
+        .map(|d| char::from(b'0' + d))
+        .skip_while(|c| *c == '0')
+        .collect();
+    // 只有`.`后面紧跟着ascii数字时才当作小数，避免把`1.jpg`中的`.jpg`当作小数部分
+    let fraction_len = s[integer_len..]
+        .strip_prefix('.')
+        .map(|after_dot| {
+            after_dot
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after_dot.len())
+        })
+        .unwrap_or_default();
+    if fraction_len == 0 {
+        return (integer, "", integer_len);
+    }
+    let fraction = &s[integer_len + 1..integer_len + 1 + fraction_len];
+    (integer, fraction, integer_len + 1 + fraction_len)
+}
+
+/// 半角数字和全角数字的值
+fn digit_value(c: char) -> Option<u8> {
+    match c {
+        '0'..='9' => Some(c as u8 - b'0'),
+        '０'..='９' => u8::try_from(c as u32 - '０' as u32).ok(),
+        _ => None,
+    }
+}
+
+fn cjk_digit_value(c: char) -> Option<u64> {
+    let value = match c {
+        '零' | '〇' => 0,
+        '一' => 1,
+        '二' | '两' => 2,
+        '三' => 3,
+        '四' => 4,
+        '五' => 5,
+        '六' => 6,
+        '七' => 7,
+        '八' => 8,
+        '九' => 9,
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn cjk_unit_value(c: char) -> Option<u64> {
+    let value = match c {
+        '十' => 10,
+        '百' => 100,
+        '千' => 1000,
+        '万' => 10_000,
+        '亿' => 100_000_000,
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn is_cjk_numeral(c: char) -> bool {
+    cjk_digit_value(c).is_some() || cjk_unit_value(c).is_some()
+}
+
+/// 将`s`开头的中文数字解析出来并与剩余部分分开，`s`不以中文数字开头时返回`None`
+/// # Example
+/// - `十二话 标题` => (12, `话 标题`)
+pub fn split_cjk_number(s: &str) -> Option<(u64, &str)> {
+    let len = s
+        .char_indices()
+        .find(|(_, c)| !is_cjk_numeral(*c))
+        .map_or(s.len(), |(i, _)| i);
+    if len == 0 {
+        return None;
+    }
+    Some((parse_cjk_number(&s[..len]), &s[len..]))
+}
+
+/// 解析中文数字
+/// # Example
+/// - `十二` => 12
+/// - `一百零五` => 105
+/// - `一二三` => 123
+fn parse_cjk_number(s: &str) -> u64 {
+    // 没有单位时逐位读取，如`一二三`
+    if !s.chars().any(|c| cjk_unit_value(c).is_some()) {
+        return s
+            .chars()
+            .filter_map(cjk_digit_value)
+            .fold(0, |acc, digit| acc.saturating_mul(10).saturating_add(digit));
+    }
+
+    // 亿及以上的部分
+    let mut total: u64 = 0;
+    // 万到亿之间的部分
+    let mut wan: u64 = 0;
+    // 万以下的部分
+    let mut section: u64 = 0;
+    let mut number: u64 = 0;
+    for c in s.chars() {
+        if let Some(digit) = cjk_digit_value(c) {
+            number = digit;
+            continue;
+        }
+        let Some(unit) = cjk_unit_value(c) else {
+            continue;
+        };
+        match unit {
+            // 亿乘以它前面的所有部分，所以`一万亿`是一万个亿
+            100_000_000 => {
+                total = total
+                    .saturating_add(wan)
+                    .saturating_add(section)
+                    .saturating_add(number)
+                    .saturating_mul(unit);
+                wan = 0;
+                section = 0;
+            }
+            10_000 => {
+                wan = wan
+                    .saturating_add(section)
+                    .saturating_add(number)
+                    .saturating_mul(unit);
+                section = 0;
+            }
+            _ => {
+                // `十二`中的`十`前面没有数字，相当于`一十`
+                let number = if number == 0 { 1 } else { number };
+                section = section.saturating_add(number.saturating_mul(unit));
+            }
+        }
+        number = 0;
+    }
+    total
+        .saturating_add(wan)
+        .saturating_add(section)
+        .saturating_add(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 断言`names`已经按自然顺序排好
+    fn assert_sorted(names: &[&str]) {
+        for pair in names.windows(2) {
+            assert_eq!(
+                compare(pair[0], pair[1]),
+                Ordering::Less,
+                "{} < {}",
+                pair[0],
+                pair[1]
+            );
+            assert_eq!(
+                compare(pair[1], pair[0]),
+                Ordering::Greater,
+                "{} > {}",
+                pair[1],
+                pair[0]
+            );
+        }
+    }
+
+    #[test]
+    fn parse_cjk_numbers() {
+        assert_eq!(parse_cjk_number("零"), 0);
+        assert_eq!(parse_cjk_number("九"), 9);
+        assert_eq!(parse_cjk_number("十"), 10);
+        assert_eq!(parse_cjk_number("十二"), 12);
+        assert_eq!(parse_cjk_number("二十"), 20);
+        assert_eq!(parse_cjk_number("两百"), 200);
+        assert_eq!(parse_cjk_number("一百零一"), 101);
+        assert_eq!(parse_cjk_number("一百一十"), 110);
+        assert_eq!(parse_cjk_number("一千零五"), 1005);
+        assert_eq!(parse_cjk_number("一万零一"), 10_001);
+        assert_eq!(parse_cjk_number("十二万三千"), 123_000);
+        assert_eq!(parse_cjk_number("三亿五千万"), 350_000_000);
+        assert_eq!(parse_cjk_number("一亿零一"), 100_000_001);
+        assert_eq!(parse_cjk_number("一万亿"), 1_000_000_000_000);
+        assert_eq!(parse_cjk_number("两万三千亿"), 2_300_000_000_000);
+        assert_eq!(parse_cjk_number("一二三"), 123);
+        assert_eq!(parse_cjk_number("二〇二四"), 2024);
+    }
+
+    #[test]
+    fn split_cjk_numbers() {
+        assert_eq!(split_cjk_number("十二话 标题"), Some((12, "话 标题")));
+        assert_eq!(split_cjk_number("一百零五"), Some((105, "")));
+        assert_eq!(split_cjk_number("话"), None);
+        assert_eq!(split_cjk_number("12话"), None);
+    }
+
+    #[test]
+    fn cjk_chapters() {
+        assert_sorted(&["第十章", "第十一章", "第二十章", "第一百零一章"]);
+        assert_sorted(&["第九话", "第十话", "第九十九话", "第一百话"]);
+    }
+
+    #[test]
+    fn ascii_numbers() {
+        assert_sorted(&["第2话", "第10话", "第100话"]);
+        assert_sorted(&["1.jpg", "2.jpg", "10.jpg"]);
+        assert_sorted(&["第1话", "第1.5话", "第2话"]);
+    }
+
+    #[test]
+    fn fullwidth_numbers() {
+        assert_sorted(&["第２话", "第１０话"]);
+        assert_eq!(compare("第１０话", "第10话"), "第１０话".cmp("第10话"));
+    }
+
+    #[test]
+    fn mixed_ascii_and_cjk_numbers() {
+        // 半角数字和中文数字按数值比较
+        assert_sorted(&["第9话", "第十话", "第11话", "第十二话"]);
+        assert_sorted(&["第一卷 第2话", "第一卷 第十话", "第二卷 第1话"]);
+    }
+
+    #[test]
+    fn leading_zeros_and_case() {
+        assert_sorted(&["001.jpg", "2.jpg", "010.jpg"]);
+        // 自然顺序相同时按原始字符串比较
+        assert_eq!(compare("01.jpg", "1.jpg"), Ordering::Less);
+        assert_sorted(&["a.jpg", "B.jpg", "c.jpg"]);
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(
+            compare_paths(
+                Path::new("漫画/第2话/1.jpg"),
+                Path::new("漫画/第10话/1.jpg")
+            ),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_paths(Path::new("漫画/第十话"), Path::new("漫画/第十话/1.jpg")),
+            Ordering::Less
+        );
+    }
+}
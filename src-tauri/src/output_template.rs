@@ -0,0 +1,288 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::anyhow;
+
+use crate::errors::{ErrorKind, ResultKindContext};
+use crate::messages;
+use crate::types::{OutputLayout, SourceFormat};
+
+/// `OutputLayout::Flattened`使用的模板，所有图片都放在漫画目录下，用章节序号和页序号保证顺序
+const FLATTENED_TEMPLATE: &str = "{manga}/{chapter_index:04}-{page:04}.{ext}";
+
+/// 渲染输出路径模板时可用的变量
+pub struct TemplateVars<'a> {
+    pub manga: &'a str,
+    pub chapter: &'a str,
+    /// 章节序号，从1开始
+    pub chapter_index: usize,
+    /// 页序号，从1开始
+    pub page: usize,
+    /// 原文件名(不含图片后缀)，见`image_stem`
+    pub stem: &'a str,
+    /// 输出图片的后缀
+    pub ext: &'a str,
+}
+
+/// 根据`layout`构建输出图片相对于输出目录的路径
+///
+/// `relative_path`是原图片相对于漫画目录的父目录的路径(漫画名/章节名/图片名)，只在`OutputLayout::Mirrored`时使用
+pub fn output_relative_path(
+    layout: OutputLayout,
+    template: &str,
+    relative_path: &Path,
+    vars: &TemplateVars,
+) -> anyhow::Result<PathBuf> {
+    match layout {
+        // 不能使用with_extension，否则`1.5`会变成`1.jpg`
+        OutputLayout::Mirrored => {
+            Ok(relative_path.with_file_name(format!("{}.{}", vars.stem, vars.ext)))
+        }
+        OutputLayout::Flattened => render(FLATTENED_TEMPLATE, vars),
+        OutputLayout::Template => render(template, vars)
+            .kind_context(ErrorKind::Config, messages::invalid_template(template)),
+    }
+}
+
+/// 图片`path`的文件名中去掉图片后缀的部分，后缀不是图片后缀(如`1.5`)或没有后缀时就是整个文件名，
+/// 这样加上输出后缀后不会和其他图片(如`1.jpg`)输出到同一个路径
+pub fn image_stem(path: &Path) -> Option<&str> {
+    let stem = if SourceFormat::from_path(path).is_some() {
+        path.file_stem()
+    } else {
+        path.file_name()
+    };
+    stem?.to_str()
+}
+
+/// 漫画名为`manga`的漫画使用`layout`时，去水印后的漫画相对于输出目录的根目录，导出时导出这个目录中的图片
+///
+/// 模板已经通过`validate`的检查，开头固定的目录中一定有`{manga}`，所以这个目录只包含这部漫画
+pub fn output_root(layout: OutputLayout, template: &str, manga: &str) -> PathBuf {
+    match layout {
+        OutputLayout::Mirrored | OutputLayout::Flattened => PathBuf::from(manga),
+        OutputLayout::Template => fixed_dirs(template)
+            .into_iter()
+            .map(|dir| dir.replace("{manga}", manga))
+            .collect(),
+    }
+}
+
+/// 模板目录部分中每部漫画固定的目录，即开头只包含`{manga}`或不包含变量的目录，遇到其他变量就停止
+fn fixed_dirs(template: &str) -> Vec<&str> {
+    let Some((dir, _)) = template.rsplit_once(['/', '\\']) else {
+        return vec![];
+    };
+    dir.split(['/', '\\'])
+        .take_while(|dir| !dir.replace("{manga}", "").contains('{'))
+        .collect()
+}
+
+/// 使用`layout`时每个章节是否有自己的输出目录，没有时无法为每个章节生成`ComicInfo.xml`
+pub fn has_chapter_dirs(layout: OutputLayout, template: &str) -> bool {
+    match layout {
+        OutputLayout::Mirrored => true,
+        OutputLayout::Flattened => false,
+        // 只有目录部分包含章节相关的变量时，每个章节才有自己的目录
+        OutputLayout::Template => template
+            .rsplit_once(['/', '\\'])
+            .is_some_and(|(dir, _)| dir.contains("{chapter")),
+    }
+}
+
+/// 检查模板`template`是否合法，用于在保存配置时和处理图片之前尽早报错
+///
+/// 除了能正常渲染，模板还必须为每部漫画创建单独的目录，否则`output_root`会包含其他漫画
+pub fn validate(template: &str) -> anyhow::Result<()> {
+    let vars = TemplateVars {
+        manga: "manga",
+        chapter: "chapter",
+        chapter_index: 1,
+        page: 1,
+        stem: "stem",
+        ext: "jpg",
+    };
+    render(template, &vars)
+        .kind_context(ErrorKind::Config, messages::invalid_template(template))?;
+    if !fixed_dirs(template)
+        .iter()
+        .any(|dir| dir.contains("{manga}"))
+    {
+        return Err(ErrorKind::Config.error(messages::template_no_manga_dir(template)));
+    }
+    Ok(())
+}
+
+/// 用`vars`渲染模板`template`
+///
+/// 变量的格式为`{name}`或`{name:width}`，`width`以`0`开头时用`0`填充，否则用空格填充，
+/// 如`{page:04}`在第7页时渲染为`0007`
+pub fn render(template: &str, vars: &TemplateVars) -> anyhow::Result<PathBuf> {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or(anyhow!(messages::template_unclosed_brace(template)))?;
+        let placeholder = &rest[start + 1..end];
+        rendered.push_str(&render_placeholder(template, placeholder, vars)?);
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    let path = PathBuf::from(rendered);
+    // 渲染结果必须是输出目录内的相对路径
+    let is_inside_output_dir = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if !is_inside_output_dir || path.as_os_str().is_empty() {
+        return Err(anyhow!(messages::template_path_outside(template, &path)));
+    }
+    Ok(path)
+}
+
+fn render_placeholder(
+    template: &str,
+    placeholder: &str,
+    vars: &TemplateVars,
+) -> anyhow::Result<String> {
+    let (name, spec) = placeholder
+        .split_once(':')
+        .map_or((placeholder, None), |(name, spec)| (name, Some(spec)));
+    let value = match name {
+        "manga" => vars.manga.to_string(),
+        "chapter" => vars.chapter.to_string(),
+        "chapter_index" => vars.chapter_index.to_string(),
+        "page" => vars.page.to_string(),
+        "stem" => vars.stem.to_string(),
+        "ext" => vars.ext.to_string(),
+        _ => return Err(anyhow!(messages::template_unknown_var(template, name))),
+    };
+    let Some(spec) = spec else {
+        return Ok(value);
+    };
+    let width: usize = spec
+        .parse()
+        .map_err(|_| anyhow!(messages::template_invalid_width(template, placeholder)))?;
+    let padded = if spec.starts_with('0') {
+        format!("{value:0>width$}")
+    } else {
+        format!("{value:>width$}")
+    };
+    Ok(padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars<'a>(stem: &'a str, ext: &'a str) -> TemplateVars<'a> {
+        TemplateVars {
+            manga: "漫画",
+            chapter: "第1话",
+            chapter_index: 3,
+            page: 7,
+            stem,
+            ext,
+        }
+    }
+
+    #[test]
+    fn render_vars_and_width() -> anyhow::Result<()> {
+        let vars = vars("001", "jpg");
+        assert_eq!(
+            render(
+                "{manga}/{chapter_index:03} {chapter}/{page:04}.{ext}",
+                &vars
+            )?,
+            Path::new("漫画/003 第1话/0007.jpg")
+        );
+        assert_eq!(
+            render("{manga}/{page:3}-{stem}.{ext}", &vars)?,
+            Path::new("漫画/  7-001.jpg")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn render_errors() {
+        let vars = vars("001", "jpg");
+        assert!(render("{manga}/{unknown}.{ext}", &vars).is_err());
+        assert!(render("{manga}/{page.{ext}", &vars).is_err());
+        assert!(render("{manga}/{page:x}.{ext}", &vars).is_err());
+        assert!(render("../{manga}/{page}.{ext}", &vars).is_err());
+        assert!(render("/{manga}/{page}.{ext}", &vars).is_err());
+        assert!(render("", &vars).is_err());
+    }
+
+    #[test]
+    fn mirrored_keeps_dotted_stems() -> anyhow::Result<()> {
+        let path = |relative_path: &str| {
+            let img_path = Path::new(relative_path);
+            let stem = image_stem(img_path).unwrap_or_default();
+            output_relative_path(OutputLayout::Mirrored, "", img_path, &vars(stem, "jpg"))
+        };
+        assert_eq!(path("漫画/第1话/1.webp")?, Path::new("漫画/第1话/1.jpg"));
+        assert_eq!(path("漫画/第1话/1.5.png")?, Path::new("漫画/第1话/1.5.jpg"));
+        // 后缀不是图片后缀时保留整个文件名
+        assert_eq!(path("漫画/第1话/1.5")?, Path::new("漫画/第1话/1.5.jpg"));
+        assert_eq!(path("漫画/第1话/1")?, Path::new("漫画/第1话/1.jpg"));
+        Ok(())
+    }
+
+    #[test]
+    fn output_roots() {
+        let root = |layout, template| output_root(layout, template, "漫画");
+        assert_eq!(root(OutputLayout::Mirrored, ""), Path::new("漫画"));
+        assert_eq!(root(OutputLayout::Flattened, ""), Path::new("漫画"));
+        assert_eq!(
+            root(OutputLayout::Template, "{manga}/{chapter}/{page}.{ext}"),
+            Path::new("漫画")
+        );
+        assert_eq!(
+            root(
+                OutputLayout::Template,
+                "去水印/{manga} 汉化/{chapter}/{page}.{ext}"
+            ),
+            Path::new("去水印/漫画 汉化")
+        );
+        assert_eq!(
+            root(
+                OutputLayout::Template,
+                "{manga}/全部/{chapter_index}-{page}.{ext}"
+            ),
+            Path::new("漫画/全部")
+        );
+    }
+
+    #[test]
+    fn validate_requires_manga_dir() {
+        assert!(validate("{manga}/{chapter_index:03} {chapter}/{page:04}.{ext}").is_ok());
+        assert!(validate("去水印/{manga}/{page}.{ext}").is_ok());
+        // 没有为每部漫画创建单独的目录
+        assert!(validate("{chapter}/{page}.{ext}").is_err());
+        assert!(validate("{manga}-{page}.{ext}").is_err());
+        assert!(validate("{manga}-{chapter}/{page}.{ext}").is_err());
+        assert!(validate("{chapter}/{manga}/{page}.{ext}").is_err());
+        assert!(validate("{manga}/{unknown}.{ext}").is_err());
+    }
+
+    #[test]
+    fn chapter_dirs() {
+        assert!(has_chapter_dirs(OutputLayout::Mirrored, ""));
+        assert!(!has_chapter_dirs(OutputLayout::Flattened, ""));
+        assert!(has_chapter_dirs(
+            OutputLayout::Template,
+            "{manga}/{chapter}/{page}.{ext}"
+        ));
+        assert!(has_chapter_dirs(
+            OutputLayout::Template,
+            "{manga}/{chapter_index:03}/{page}.{ext}"
+        ));
+        assert!(!has_chapter_dirs(
+            OutputLayout::Template,
+            "{manga}/{chapter}-{page}.{ext}"
+        ));
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use tauri::{AppHandle, Manager};
+use walkdir::WalkDir;
+
+use crate::messages;
+
+/// 资源目录中存在这个文件时使用便携模式，配置文件和背景水印图都保存在资源目录中
+const PORTABLE_MARKER: &str = "portable";
+pub const CONFIG_FILE_NAME: &str = "config.json";
+pub const BACKGROUND_DIR_NAME: &str = "背景水印图";
+
+pub fn is_portable(app: &AppHandle) -> anyhow::Result<bool> {
+    let resource_dir = app.path().resource_dir()?;
+    Ok(resource_dir.join(PORTABLE_MARKER).is_file())
+}
+
+/// 配置文件所在的目录，便携模式下是资源目录，否则是系统的配置目录
+pub fn config_dir(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    if is_portable(app)? {
+        return Ok(app.path().resource_dir()?);
+    }
+    Ok(app.path().app_config_dir()?)
+}
+
+/// 背景水印图、任务队列、监视目录和日志所在的目录，便携模式下是资源目录，否则是系统的数据目录
+pub fn data_dir(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    if is_portable(app)? {
+        return Ok(app.path().resource_dir()?);
+    }
+    Ok(app.path().app_data_dir()?)
+}
+
+/// 扫描缓存所在的目录，便携模式下是资源目录，否则是系统的缓存目录
+pub fn cache_dir(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    if is_portable(app)? {
+        return Ok(app.path().resource_dir()?);
+    }
+    Ok(app.path().app_cache_dir()?)
+}
+
+pub fn config_path(app: &AppHandle) -> anyhow::Result<PathBuf> {
+    Ok(config_dir(app)?.join(CONFIG_FILE_NAME))
+}
+
+/// 以前的版本把配置文件和背景水印图保存在资源目录中，系统安装时资源目录是只读的，所以把它们复制到新的位置
+///
+/// 新的位置已经存在时不会复制，所以只会迁移一次，资源目录中的旧文件能删除就删除，删除失败不影响使用
+pub fn migrate(app: &AppHandle) -> anyhow::Result<()> {
+    if is_portable(app)? {
+        return Ok(());
+    }
+    let resource_dir = app.path().resource_dir()?;
+
+    let old_config_path = resource_dir.join(CONFIG_FILE_NAME);
+    let new_config_path = config_path(app)?;
+    if old_config_path.is_file() && !new_config_path.exists() {
+        let config_dir = config_dir(app)?;
+        std::fs::create_dir_all(&config_dir).context(messages::create_dir_failed(&config_dir))?;
+        std::fs::copy(&old_config_path, &new_config_path)
+            .context(messages::copy_failed(&old_config_path, &new_config_path))?;
+        let _ = std::fs::remove_file(&old_config_path);
+        tracing::info!(
+            from = ?old_config_path,
+            to = ?new_config_path,
+            "{}",
+            messages::log_migrated()
+        );
+    }
+
+    if new_config_path.is_file() {
+        migrate_output_dir(&new_config_path, &resource_dir, &data_dir(app)?)?;
+    }
+
+    let old_background_dir = resource_dir.join(BACKGROUND_DIR_NAME);
+    let new_background_dir = data_dir(app)?.join(BACKGROUND_DIR_NAME);
+    if old_background_dir.is_dir() && !new_background_dir.exists() {
+        copy_dir(&old_background_dir, &new_background_dir)?;
+        let _ = std::fs::remove_dir_all(&old_background_dir);
+        tracing::info!(
+            from = ?old_background_dir,
+            to = ?new_background_dir,
+            "{}",
+            messages::log_migrated()
+        );
+    }
+
+    Ok(())
+}
+
+/// 以前的版本默认输出到资源目录，系统安装时资源目录是只读的，配置中的输出目录还是资源目录时改为新的数据目录
+fn migrate_output_dir(
+    config_path: &Path,
+    resource_dir: &Path,
+    data_dir: &Path,
+) -> anyhow::Result<()> {
+    let config_string =
+        std::fs::read_to_string(config_path).context(messages::read_config_failed(config_path))?;
+    // 配置文件无法解析时会使用默认配置，默认的输出目录已经是数据目录
+    let Ok(mut config) = serde_json::from_str::<serde_json::Value>(&config_string) else {
+        return Ok(());
+    };
+    let Some(output_dir) = config.get_mut("outputDir") else {
+        return Ok(());
+    };
+    if output_dir.as_str().map(Path::new) != Some(resource_dir) {
+        return Ok(());
+    }
+    *output_dir = serde_json::to_value(data_dir)?;
+    let config_string = serde_json::to_string_pretty(&config)?;
+    std::fs::write(config_path, config_string)
+        .context(messages::save_config_failed(config_path))?;
+    tracing::info!(
+        from = ?resource_dir,
+        to = ?data_dir,
+        "{}",
+        messages::log_output_dir_migrated()
+    );
+    Ok(())
+}
+
+/// 把`from`目录中的所有内容复制到`to`，先复制到临时目录再重命名，中途失败不会留下不完整的`to`
+fn copy_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    let tmp_dir = to.with_extension("tmp");
+    if tmp_dir.exists() {
+        std::fs::remove_dir_all(&tmp_dir).context(messages::delete_failed(&tmp_dir))?;
+    }
+    for entry in WalkDir::new(from) {
+        let entry = entry.context(messages::read_dir_failed(from))?;
+        let relative_path = entry
+            .path()
+            .strip_prefix(from)
+            .context(messages::not_parent_dir(from, entry.path()))?;
+        let target = tmp_dir.join(relative_path);
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target).context(messages::create_dir_failed(&target))?;
+        } else {
+            std::fs::copy(entry.path(), &target)
+                .context(messages::copy_failed(entry.path(), &target))?;
+        }
+    }
+    std::fs::rename(&tmp_dir, to).context(messages::rename_failed(&tmp_dir, to))?;
+    Ok(())
+}
@@ -0,0 +1,356 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use parking_lot::{Mutex, RwLock};
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+
+use crate::config::Config;
+use crate::events::QueueUpdateEvent;
+use crate::extensions::AnyhowErrorToStringChain;
+use crate::messages;
+use crate::paths;
+use crate::remover::{
+    self, Backgrounds, MangaClaim, ProcessingResources, RemoveWatermarkOptions,
+    RemoveWatermarkOutcome,
+};
+use crate::scan_cache::ScanCache;
+use crate::types::{ConflictPolicy, ImageFormat, JobStatus, QueueJob, QueueState};
+
+/// 等待中的任务的漫画正在被其他地方去水印时，隔多久再检查一次
+const DEFER_INTERVAL: Duration = Duration::from_secs(1);
+
+struct QueueInner {
+    state: QueueState,
+    // 正在执行任务的线程数量
+    workers: u32,
+}
+
+/// 持久化的去水印任务队列，每个任务处理一个漫画目录，队列内容在重启后依然保留
+pub struct JobQueue {
+    path: PathBuf,
+    inner: Mutex<QueueInner>,
+}
+
+impl JobQueue {
+    pub fn load(app: &AppHandle) -> anyhow::Result<Self> {
+        let data_dir = paths::data_dir(app)?;
+        let path = data_dir.join("queue.json");
+        // 队列文件损坏时直接丢弃
+        let mut state: QueueState = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|queue_string| serde_json::from_str(&queue_string).ok())
+            .unwrap_or_default();
+        // 上次退出时还在执行的任务被中断了，重新排队
+        for job in &mut state.jobs {
+            if job.status == JobStatus::Running {
+                job.status = JobStatus::Pending;
+            }
+        }
+        state.running = false;
+        Ok(Self {
+            path,
+            inner: Mutex::new(QueueInner { state, workers: 0 }),
+        })
+    }
+
+    pub fn state(&self) -> QueueState {
+        self.inner.lock().state.clone()
+    }
+
+    /// 添加一个任务到队列末尾，同一个漫画目录不能同时有多个未完成的任务
+    #[allow(clippy::too_many_arguments)]
+    pub fn enqueue(
+        &self,
+        app: &AppHandle,
+        manga_dir: PathBuf,
+        output_dir: PathBuf,
+        format: ImageFormat,
+        optimize: bool,
+        backgrounds: Vec<(PathBuf, PathBuf)>,
+        comic_info: bool,
+        conflict_policy: ConflictPolicy,
+    ) -> anyhow::Result<QueueJob> {
+        let output_settings = app.state::<RwLock<Config>>().read().output_settings();
+        let job = QueueJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            manga_dir,
+            output_dir,
+            format,
+            optimize,
+            backgrounds,
+            comic_info,
+            conflict_policy,
+            output_settings: Some(output_settings),
+            status: JobStatus::Pending,
+            error: None,
+            failures: vec![],
+            report_id: None,
+        };
+        self.update(app, |state| {
+            let unfinished = state.jobs.iter().any(|queued| {
+                queued.manga_dir == job.manga_dir
+                    && matches!(queued.status, JobStatus::Pending | JobStatus::Running)
+            });
+            if unfinished {
+                return Err(anyhow!(messages::job_already_queued(&job.manga_dir)));
+            }
+            state.jobs.push(job.clone());
+            Ok(())
+        })?;
+        Ok(job)
+    }
+
+    /// 将任务`id`移动到队列中的位置`index`，`index`超出范围时移动到末尾
+    pub fn move_job(&self, app: &AppHandle, id: &str, index: usize) -> anyhow::Result<()> {
+        self.update(app, |state| {
+            let from = position(state, id)?;
+            let job = state.jobs.remove(from);
+            let index = index.min(state.jobs.len());
+            state.jobs.insert(index, job);
+            Ok(())
+        })
+    }
+
+    /// 从队列中删除任务`id`，正在执行的任务不能删除
+    pub fn remove_job(&self, app: &AppHandle, id: &str) -> anyhow::Result<()> {
+        self.update(app, |state| {
+            let index = position(state, id)?;
+            if state.jobs[index].status == JobStatus::Running {
+                return Err(anyhow!(messages::job_running(id)));
+            }
+            state.jobs.remove(index);
+            Ok(())
+        })
+    }
+
+    /// 将失败的任务`id`重新排队，下次执行时从头处理整个漫画目录
+    pub fn retry_job(&self, app: &AppHandle, id: &str) -> anyhow::Result<()> {
+        self.update(app, |state| {
+            let index = position(state, id)?;
+            let manga_dir = &state.jobs[index].manga_dir;
+            if state.jobs[index].status != JobStatus::Failed {
+                return Err(anyhow!(messages::job_not_failed(id)));
+            }
+            // 失败后可能又为同一个漫画目录添加了任务
+            let unfinished = state.jobs.iter().any(|queued| {
+                &queued.manga_dir == manga_dir
+                    && matches!(queued.status, JobStatus::Pending | JobStatus::Running)
+            });
+            if unfinished {
+                return Err(anyhow!(messages::job_already_queued(manga_dir)));
+            }
+            let job = &mut state.jobs[index];
+            job.status = JobStatus::Pending;
+            job.error = None;
+            job.failures = vec![];
+            job.report_id = None;
+            Ok(())
+        })
+    }
+
+    /// 删除所有已完成和失败的任务
+    pub fn clear_finished(&self, app: &AppHandle) -> anyhow::Result<()> {
+        self.update(app, |state| {
+            state
+                .jobs
+                .retain(|job| matches!(job.status, JobStatus::Pending | JobStatus::Running));
+            Ok(())
+        })
+    }
+
+    /// 开始执行队列，最多同时执行`concurrency`个任务
+    pub fn start(&self, app: &AppHandle, concurrency: u32) -> anyhow::Result<()> {
+        let new_workers = {
+            let mut inner = self.inner.lock();
+            inner.state.running = true;
+            let new_workers = concurrency.max(1).saturating_sub(inner.workers);
+            inner.workers += new_workers;
+            new_workers
+        };
+        for _ in 0..new_workers {
+            let app = app.clone();
+            std::thread::spawn(move || run_worker(&app));
+        }
+        self.notify(app)
+    }
+
+    /// 暂停队列，正在执行的任务会继续执行完，之后不再开始新的任务
+    pub fn pause(&self, app: &AppHandle) -> anyhow::Result<()> {
+        self.inner.lock().state.running = false;
+        self.notify(app)
+    }
+
+    /// 取出下一个等待中的任务并标记为正在执行，队列暂停或没有等待中的任务时返回`None`，调用者应该退出
+    ///
+    /// 漫画正在被监视或手动去水印的任务会被推迟，只有等待中的任务全都被推迟时才等待，返回的`MangaClaim`要保留到任务结束
+    fn next_job<'r>(
+        &self,
+        app: &AppHandle,
+        resources: &'r ProcessingResources,
+    ) -> Option<(QueueJob, MangaClaim<'r>)> {
+        let job = loop {
+            let mut inner = self.inner.lock();
+            let running = inner.state.running;
+            let mut deferred = false;
+            let mut next = None;
+            if running {
+                for job in &mut inner.state.jobs {
+                    if job.status != JobStatus::Pending {
+                        continue;
+                    }
+                    match resources.claim(&job.manga_dir) {
+                        Some(claim) => {
+                            job.status = JobStatus::Running;
+                            next = Some((job.clone(), claim));
+                            break;
+                        }
+                        None => deferred = true,
+                    }
+                }
+            }
+            match next {
+                Some(next) => break Some(next),
+                None if deferred => {
+                    drop(inner);
+                    std::thread::sleep(DEFER_INTERVAL);
+                }
+                None => {
+                    inner.workers -= 1;
+                    // 最后一个线程退出时，如果所有任务都执行完了，则队列停止
+                    let all_done = !inner
+                        .state
+                        .jobs
+                        .iter()
+                        .any(|job| job.status == JobStatus::Pending);
+                    if inner.workers == 0 && all_done {
+                        inner.state.running = false;
+                    }
+                    break None;
+                }
+            }
+        };
+        self.persist(app);
+        job
+    }
+
+    /// 记录任务`id`的执行结果
+    fn finish_job(
+        &self,
+        app: &AppHandle,
+        id: &str,
+        result: anyhow::Result<RemoveWatermarkOutcome>,
+    ) {
+        {
+            let mut inner = self.inner.lock();
+            // 执行过程中任务可能被移动，但正在执行的任务不会被删除
+            if let Some(job) = inner.state.jobs.iter_mut().find(|job| job.id == id) {
+                match result {
+                    // 开启了出错后继续时，即使有图片失败任务也算完成
+                    Ok(outcome) => {
+                        job.status = JobStatus::Completed;
+                        job.failures = outcome.failures();
+                        job.report_id = Some(outcome.report.id);
+                    }
+                    Err(err) => {
+                        job.status = JobStatus::Failed;
+                        job.error = Some(err.to_string_chain());
+                    }
+                }
+            }
+        }
+        self.persist(app);
+    }
+
+    /// 在执行任务的线程中保存队列并发送`QueueUpdateEvent`
+    fn persist(&self, app: &AppHandle) {
+        // 这里的错误无法返回给前端，保存失败时队列依然保留在内存中，下次修改时会再次保存
+        let _ = self.save();
+        let _ = self.notify(app);
+    }
+
+    /// 修改队列，修改成功后保存队列并发送`QueueUpdateEvent`
+    fn update(
+        &self,
+        app: &AppHandle,
+        f: impl FnOnce(&mut QueueState) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        f(&mut self.inner.lock().state)?;
+        self.save()?;
+        self.notify(app)
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(messages::create_dir_failed(parent))?;
+        }
+        // 多个执行任务的线程可能同时保存，持有锁直到重命名完成，避免同时写入临时文件
+        let inner = self.inner.lock();
+        let queue_string = serde_json::to_string_pretty(&inner.state)?;
+        // 先写入临时文件再重命名，保存到一半时退出也不会损坏队列文件
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, queue_string).context(messages::save_queue_failed(&tmp_path))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .context(messages::rename_failed(&tmp_path, &self.path))?;
+        Ok(())
+    }
+
+    fn notify(&self, app: &AppHandle) -> anyhow::Result<()> {
+        QueueUpdateEvent(self.state()).emit(app)?;
+        Ok(())
+    }
+}
+
+fn position(state: &QueueState, id: &str) -> anyhow::Result<usize> {
+    state
+        .jobs
+        .iter()
+        .position(|job| job.id == id)
+        .ok_or(anyhow!(messages::job_not_found(id)))
+}
+
+/// 不断从队列中取出任务执行，直到队列暂停或没有等待中的任务
+fn run_worker(app: &AppHandle) {
+    let queue = app.state::<JobQueue>();
+    let resources = app.state::<ProcessingResources>();
+    while let Some((job, _claim)) = queue.next_job(app, &resources) {
+        tracing::info!(
+            job_id = job.id,
+            manga_dir = ?job.manga_dir,
+            "{}",
+            messages::log_job_started()
+        );
+        let result = run_job(app, &job);
+        if let Err(err) = &result {
+            tracing::error!(
+                job_id = job.id,
+                err_msg = err.to_string_chain(),
+                "{}",
+                messages::log_job_failed()
+            );
+        }
+        queue.finish_job(app, &job.id, result);
+    }
+}
+
+fn run_job(app: &AppHandle, job: &QueueJob) -> anyhow::Result<RemoveWatermarkOutcome> {
+    let config = app.state::<RwLock<Config>>();
+    let scan_cache = app.state::<ScanCache>();
+    // 输出结构、原地处理等设置使用加入队列时的设置，线程数等资源设置在执行时从配置中读取
+    let config = match &job.output_settings {
+        Some(output_settings) => config.read().with_output_settings(output_settings),
+        None => config.read().clone(),
+    };
+    let options = RemoveWatermarkOptions::new(
+        &config,
+        &job.manga_dir,
+        &job.output_dir,
+        job.format.clone(),
+        job.optimize,
+        job.comic_info,
+        job.conflict_policy,
+    );
+    let backgrounds = Backgrounds::open(&job.backgrounds)?;
+    remover::remove_watermark(app, &options, &backgrounds, &scan_cache)
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+
+use anyhow::{anyhow, Context};
+use parking_lot::Mutex;
+use rayon::ThreadPool;
+use tauri::AppHandle;
+use tauri_specta::Event;
+
+use crate::backup::Backup;
+use crate::comic_info::ComicInfo;
+use crate::events;
+use crate::extensions::AnyhowErrorToStringChain;
+use crate::manifest::{Manifest, ManifestEntry, SourceStamp};
+use crate::messages;
+use crate::output_template;
+use crate::remover::pipeline::{Pipeline, WrittenPage};
+use crate::remover::progress::{ProgressTracker, PROGRESS_INTERVAL};
+use crate::remover::{Backgrounds, MemoryBudget, RemoveWatermarkOptions};
+use crate::types::{ChapterPlan, PageAction, PageOutcome, PageReport, RemoveWatermarkPlan};
+
+/// 按照`plan`去除水印，所有图片经过同一条流水线处理，流水线中的计算在`pool`中执行，章节的图片全部写入后收尾这个章节，按规划的顺序返回每张图片的处理结果
+#[allow(clippy::too_many_arguments)]
+pub fn execute(
+    app: &AppHandle,
+    options: &RemoveWatermarkOptions,
+    backgrounds: &Backgrounds,
+    plan: &RemoveWatermarkPlan,
+    manifest: &Manifest,
+    backup: Option<&Backup>,
+    pool: &ThreadPool,
+    budget: &MemoryBudget,
+) -> anyhow::Result<Vec<PageReport>> {
+    let (manga_name, _) = options.manga_name_and_parent()?;
+    // dir => (current, total)
+    let dir_progress = create_dir_progress(app, &plan.chapters)?;
+    // 使用Mutex包装dir_progress，用于在多个写入线程中更新目录的进度
+    let dir_progress = Mutex::new(dir_progress);
+    let tracker = ProgressTracker::new(options.manga_dir.clone(), plan);
+    let tracker = &tracker;
+    // 多个章节可能同时写完，保存清单和备份索引时需要加锁，避免同时写同一个文件
+    let save_lock = Mutex::new(());
+    // img_path => 这张图片的处理结果，写入的顺序不固定，最后再按规划的顺序排列
+    let page_reports = Mutex::new(HashMap::new());
+    let on_written = |written: WrittenPage| -> anyhow::Result<()> {
+        let chapter = &plan.chapters[written.chapter_index];
+        let dir = &chapter.dir;
+        let page = written.page;
+        tracker.record(&written);
+        let error = written.error.as_ref().map(|err| err.to_string_chain());
+        // 失败的图片不记录到清单中，下次会重新处理
+        if error.is_none() && page.action != PageAction::Skip {
+            record_page(options, backgrounds, manifest, backup, &written)?;
+        }
+        let report = page_report(backgrounds, &written, dir, error);
+        // 更新目录的进度
+        let (current, total) = {
+            let mut dir_progress = dir_progress.lock();
+            let (current, total) = dir_progress
+                .get_mut(dir)
+                .ok_or(anyhow!(messages::dir_progress_missing(dir)))?;
+            *current += 1;
+            (*current, *total)
+        };
+        if let Some(err_msg) = &report.error {
+            tracing::warn!(img_path = ?page.img_path, err_msg, "{}", messages::log_page_failed());
+            // 发送RemoveWatermarkErrorEvent事件
+            let payload = events::RemoveWatermarkErrorEventPayload {
+                dir_path: dir.clone(),
+                img_path: page.img_path.clone(),
+                err_msg: err_msg.clone(),
+            };
+            let event = events::RemoveWatermarkErrorEvent(payload);
+            event.emit(app)?;
+        } else {
+            // 发送RemoveWatermarkSuccessEvent事件
+            let payload = events::RemoveWatermarkSuccessEventPayload {
+                dir_path: dir.clone(),
+                img_path: page.out_image_path.clone(),
+                current,
+                conflict_resolution: page.conflict_resolution,
+            };
+            let event = events::RemoveWatermarkSuccessEvent(payload);
+            event.emit(app)?;
+        }
+        page_reports.lock().insert(page.img_path.clone(), report);
+        // 如果当前图片是目录下的最后一张图片，则发送RemoveWatermarkEndEvent事件并收尾这个章节
+        if current == total {
+            let payload = events::RemoveWatermarkEndEventPayload {
+                dir_path: dir.clone(),
+            };
+            let event = events::RemoveWatermarkEndEvent(payload);
+            event.emit(app)?;
+            let _save_guard = save_lock.lock();
+            finish_chapter(options, manga_name, chapter, manifest, backup)?;
+        }
+        Ok(())
+    };
+    let pages = plan
+        .chapters
+        .iter()
+        .enumerate()
+        .flat_map(|(i, chapter)| chapter.pages.iter().map(move |page| (i, page)))
+        .collect();
+    let pipeline = Pipeline {
+        options,
+        backgrounds,
+        backup,
+        pool,
+        budget,
+        on_written: &on_written,
+    };
+    let stages = std::thread::scope(|scope| {
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+        // 定时发送整体进度，直到流水线结束
+        scope.spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(PROGRESS_INTERVAL)
+            {
+                // 进度只是用于显示，发送失败也不影响去水印
+                let _ = events::RemoveWatermarkProgressEvent(tracker.progress()).emit(app);
+            }
+        });
+        let result = pipeline.run(pages);
+        drop(stop_sender);
+        result
+    })?;
+    // 发送最终的RemoveWatermarkProgressEvent事件，保证前端看到的进度是100%
+    let event = events::RemoveWatermarkProgressEvent(tracker.progress());
+    event.emit(app)?;
+    // 发送PipelineStatsEvent事件
+    let payload = events::PipelineStatsEventPayload {
+        manga_dir: options.manga_dir.clone(),
+        stages,
+    };
+    let event = events::PipelineStatsEvent(payload);
+    event.emit(app)?;
+    // 发送RemoveWatermarkSummaryEvent事件
+    let event = events::RemoveWatermarkSummaryEvent(tracker.summary());
+    event.emit(app)?;
+    let mut page_reports = page_reports.into_inner();
+    let page_reports = plan
+        .chapters
+        .iter()
+        .flat_map(|chapter| &chapter.pages)
+        .filter_map(|page| page_reports.remove(&page.img_path))
+        .collect();
+    Ok(page_reports)
+}
+
+/// 根据写入的结果生成这张图片的报告
+#[allow(clippy::cast_possible_truncation)]
+fn page_report(
+    backgrounds: &Backgrounds,
+    written: &WrittenPage,
+    dir_path: &Path,
+    error: Option<String>,
+) -> PageReport {
+    let page = written.page;
+    let outcome = match page.action {
+        _ if error.is_some() => PageOutcome::Failed,
+        PageAction::Skip => PageOutcome::Skipped,
+        _ if written.watermark_removed => PageOutcome::Removed,
+        _ => PageOutcome::Copied,
+    };
+    let background_version = match outcome {
+        PageOutcome::Removed => backgrounds
+            .version(page.width, page.height)
+            .map(str::to_string),
+        _ => None,
+    };
+    PageReport {
+        dir_path: dir_path.to_path_buf(),
+        img_path: page.img_path.clone(),
+        out_image_path: page.out_image_path.clone(),
+        outcome,
+        width: page.width,
+        height: page.height,
+        background_version,
+        output_bytes: written.written_bytes,
+        duration_ms: written.duration.as_millis() as u64,
+        error,
+    }
+}
+
+/// 在清单中记录图片这次的输出，原地处理时还要在备份中记录
+fn record_page(
+    options: &RemoveWatermarkOptions,
+    backgrounds: &Backgrounds,
+    manifest: &Manifest,
+    backup: Option<&Backup>,
+    written: &WrittenPage,
+) -> anyhow::Result<()> {
+    let page = written.page;
+    if let Some(backup) = backup {
+        backup.record_output(&page.img_path, &page.out_image_path)?;
+    }
+    // 记录这次写入的输出，下次处理时用于判断是否可以跳过
+    let (settings, background_version) = if written.watermark_removed {
+        (
+            options.settings(),
+            backgrounds
+                .version(page.width, page.height)
+                .map(str::to_string),
+        )
+    } else {
+        ("copy".to_string(), None)
+    };
+    let content_hash = written
+        .content_hash
+        .clone()
+        .ok_or(anyhow!(messages::page_not_read(&page.img_path)))?;
+    let stamp = SourceStamp::read(&written.source_path)?;
+    let entry = ManifestEntry {
+        target_path: page.target_path.clone(),
+        output_path: page.out_image_path.clone(),
+        size: stamp.size,
+        modified_secs: stamp.modified_secs,
+        modified_nanos: stamp.modified_nanos,
+        content_hash,
+        settings,
+        background_version,
+    };
+    manifest.record(written.source_path.clone(), entry);
+    Ok(())
+}
+
+/// 章节的图片全部处理完后保存清单，即使之后出错，下次也能跳过已处理的图片，然后写入ComicInfo.xml
+fn finish_chapter(
+    options: &RemoveWatermarkOptions,
+    manga_name: &str,
+    chapter: &ChapterPlan,
+    manifest: &Manifest,
+    backup: Option<&Backup>,
+) -> anyhow::Result<()> {
+    manifest.save()?;
+    if let Some(backup) = backup {
+        backup.save_index()?;
+    }
+    let output_chapter_dir = chapter
+        .pages
+        .first()
+        .and_then(|page| page.out_image_path.parent());
+    if options.comic_info && output_template::has_chapter_dirs(options.layout, &options.template) {
+        if let Some(output_chapter_dir) = output_chapter_dir {
+            save_comic_info(
+                manga_name,
+                &chapter.name,
+                output_chapter_dir,
+                chapter.page_count,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// 在章节的输出目录`output_chapter_dir`中写入`ComicInfo.xml`
+fn save_comic_info(
+    manga_name: &str,
+    chapter_name: &str,
+    output_chapter_dir: &Path,
+    page_count: u32,
+) -> anyhow::Result<()> {
+    ComicInfo::new(manga_name, chapter_name, page_count)
+        .save(output_chapter_dir)
+        .context(messages::save_comic_info_failed(output_chapter_dir))?;
+    Ok(())
+}
+
+/// 构建一个`HashMap`，`key`是目录的路径，`value`是该目录的进度(current, total)，并按顺序为每个目录发送`RemoveWatermarkStartEvent`
+#[allow(clippy::cast_possible_truncation)]
+fn create_dir_progress<'a>(
+    app: &AppHandle,
+    chapters: &'a [ChapterPlan],
+) -> anyhow::Result<HashMap<&'a PathBuf, (u32, u32)>> {
+    let dir_progress: HashMap<&PathBuf, (u32, u32)> = chapters
+        .iter()
+        .map(|chapter| {
+            let total = chapter.pages.len() as u32;
+            // 发送RemoveWatermarkStartEvent事件
+            let payload = events::RemoveWatermarkStartEventPayload {
+                dir_path: chapter.dir.clone(),
+                total,
+            };
+            let event = events::RemoveWatermarkStartEvent(payload);
+            event.emit(app).map_err(anyhow::Error::from)?;
+
+            Ok((&chapter.dir, (0, total)))
+        })
+        .collect::<anyhow::Result<HashMap<&PathBuf, (u32, u32)>>>()?;
+    Ok(dir_progress)
+}
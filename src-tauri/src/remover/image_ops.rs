@@ -0,0 +1,103 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use image::codecs::png::PngEncoder;
+use image::{Rgb, RgbImage};
+
+use crate::messages;
+use crate::types::ImageFormat;
+
+/// 去除`img`的水印
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_lossless)]
+#[allow(clippy::cast_sign_loss)]
+pub fn remove_image_watermark(black: &RgbImage, white: &RgbImage, img: &mut RgbImage) {
+    if img.width() != white.width() || img.height() != white.height() {
+        return;
+    }
+    // 遍历图片的每个像素点
+    let [black_in_r, black_in_g, black_in_b] = black.get_pixel(0, 0).0.map(|x| x as f64);
+    for (x, y, img_pixel) in img.enumerate_pixels_mut() {
+        let [out_r, out_g, out_b] = img_pixel.0.map(|x| x as f64);
+        let [black_out_r, black_out_g, black_out_b] = black.get_pixel(x, y).0.map(|x| x as f64);
+        let [white_out_r, white_out_g, white_out_b] = white.get_pixel(x, y).0.map(|x| x as f64);
+
+        let in_r = (out_r - black_out_r) / ((white_out_r - black_out_r) / 255.0) + black_in_r;
+        let in_g = (out_g - black_out_g) / ((white_out_g - black_out_g) / 255.0) + black_in_g;
+        let in_b = (out_b - black_out_b) / ((white_out_b - black_out_b) / 255.0) + black_in_b;
+        // 将f64转换为u8自带clamp功能
+        let watermark_removed_r = in_r.round() as u8;
+        let watermark_removed_g = in_g.round() as u8;
+        let watermark_removed_b = in_b.round() as u8;
+        // 将去除水印后的像素点赋值给img
+        *img_pixel = Rgb([
+            watermark_removed_r,
+            watermark_removed_g,
+            watermark_removed_b,
+        ]);
+    }
+}
+
+/// 将图片`img`编码为`format`格式，`optimize`为true时会检查图片是否为灰度图像，如果是则编码为luma8图片
+pub fn encode_image(
+    img: &RgbImage,
+    format: &ImageFormat,
+    optimize: bool,
+) -> anyhow::Result<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => encode_jpg_image(img, optimize),
+        ImageFormat::Png => encode_png_image(img, optimize),
+    }
+}
+
+/// 编码后的图片保存的路径，保证后缀与`format`一致
+pub fn with_output_extension(path: &Path, format: &ImageFormat) -> PathBuf {
+    match format {
+        ImageFormat::Jpeg => path.with_extension("jpg"),
+        ImageFormat::Png => path.with_extension("png"),
+    }
+}
+
+/// 将图片`img`编码为jpg, `optimize`为true时会检查图片是否为灰度图像，如果是则编码为luma8图片
+#[allow(clippy::cast_possible_truncation)]
+fn encode_jpg_image(img: &RgbImage, optimize: bool) -> anyhow::Result<Vec<u8>> {
+    let (width, height) = (img.width() as u16, img.height() as u16);
+    let mut data = vec![];
+    // 使用jpeg_encoder库的Encoder处理jpg效率更高
+    let encoder = jpeg_encoder::Encoder::new(&mut data, 95);
+    if optimize && is_grey_image(img) {
+        // 直接从img转换，避免克隆整张图片
+        let luma = image::imageops::grayscale(img);
+        encoder
+            .encode(luma.as_raw(), width, height, jpeg_encoder::ColorType::Luma)
+            .context(messages::encode_luma8_failed())?;
+    } else {
+        encoder
+            .encode(img.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+            .context(messages::encode_rgb_failed())?;
+    }
+    Ok(data)
+}
+
+/// 将图片`img`编码为png, `optimize`为true时会检查图片是否为灰度图像，如果是则编码为luma8图片
+fn encode_png_image(img: &RgbImage, optimize: bool) -> anyhow::Result<Vec<u8>> {
+    let mut data = vec![];
+    let encoder = PngEncoder::new(&mut data);
+    if optimize && is_grey_image(img) {
+        // 直接从img转换，避免克隆整张图片
+        let luma = image::imageops::grayscale(img);
+        luma.write_with_encoder(encoder)
+            .context(messages::encode_luma8_failed())?;
+    } else {
+        img.write_with_encoder(encoder)
+            .context(messages::encode_rgb_failed())?;
+    }
+    Ok(data)
+}
+
+fn is_grey_image(img: &RgbImage) -> bool {
+    img.pixels().all(|pixel| {
+        let [r, g, b] = pixel.0;
+        r == g && g == b
+    })
+}
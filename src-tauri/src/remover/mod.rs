@@ -0,0 +1,496 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{anyhow, Context};
+use image::RgbImage;
+use rayon::ThreadPool;
+use tauri::{AppHandle, Manager};
+
+use crate::backup::Backup;
+use crate::config::Config;
+use crate::errors::ErrorKind;
+use crate::export;
+use crate::extensions::AnyhowErrorToStringChain;
+use crate::manifest::{self, Manifest};
+use crate::messages;
+use crate::output_template;
+use crate::report::JobHistory;
+use crate::scan_cache::ScanCache;
+use crate::types::{
+    BackupMode, ConflictPolicy, ExportFormat, ExportScope, ImageData, ImageFormat, JobReport,
+    OutputLayout, PageFailure, PageOutcome, PipelineThreads, RemoveWatermarkPlan, ReportBackground,
+    ScanOptions,
+};
+use crate::utils;
+
+mod execute;
+mod image_ops;
+mod pipeline;
+mod plan;
+mod progress;
+mod resources;
+
+pub use execute::execute;
+pub use plan::plan;
+pub use resources::{MangaClaim, MemoryBudget, ProcessingResources};
+
+/// 一次去水印所需的全部设置，由命令的参数和`Config`中的设置组成
+pub struct RemoveWatermarkOptions {
+    pub manga_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub format: ImageFormat,
+    pub optimize: bool,
+    pub comic_info: bool,
+    pub conflict_policy: ConflictPolicy,
+    pub layout: OutputLayout,
+    pub template: String,
+    pub incremental: bool,
+    pub in_place: bool,
+    pub backup_mode: BackupMode,
+    pub scan_options: ScanOptions,
+    // 不为`None`时只处理其中的图片，其他图片依然参与章节序号和页序号的计算
+    pub only_pages: Option<HashSet<PathBuf>>,
+    // 线程池的线程数，为0时使用CPU的逻辑核心数
+    pub worker_threads: usize,
+    // 同时解码的图片最多占用的字节数，为0时不限制
+    pub memory_budget: u64,
+    // 流水线中每个阶段的线程数
+    pub pipeline_threads: PipelineThreads,
+    // 单张图片处理失败时是否继续处理其他图片
+    pub continue_on_error: bool,
+    // 去水印后导出的格式，为`None`时不导出
+    pub export_format: Option<ExportFormat>,
+    pub export_scope: ExportScope,
+    // 导出的文件保存在这个目录中，原地处理时也是设置的输出目录
+    pub export_dir: PathBuf,
+}
+
+impl RemoveWatermarkOptions {
+    pub fn new(
+        config: &Config,
+        manga_dir: &Path,
+        output_dir: &Path,
+        format: ImageFormat,
+        optimize: bool,
+        comic_info: bool,
+        conflict_policy: ConflictPolicy,
+    ) -> Self {
+        let output_dir_setting = output_dir;
+        // 原地处理时不使用output_dir，输出直接写回漫画目录，并且必须覆盖原图片
+        let (output_dir, conflict_policy) = match manga_dir.parent() {
+            Some(manga_dir_without_name) if config.in_place => (
+                manga_dir_without_name.to_path_buf(),
+                ConflictPolicy::Overwrite,
+            ),
+            _ => (output_dir.to_path_buf(), conflict_policy),
+        };
+        Self {
+            manga_dir: manga_dir.to_path_buf(),
+            output_dir,
+            format,
+            optimize,
+            comic_info,
+            conflict_policy,
+            layout: config.output_layout,
+            template: config.output_template.clone(),
+            incremental: config.output_incremental,
+            in_place: config.in_place,
+            backup_mode: config.backup_mode,
+            scan_options: config.scan_options.clone(),
+            only_pages: None,
+            worker_threads: config.worker_threads as usize,
+            memory_budget: u64::from(config.memory_budget_mb) * 1024 * 1024,
+            pipeline_threads: config.pipeline_threads,
+            continue_on_error: config.continue_on_error,
+            export_format: config.export_format,
+            export_scope: config.export_scope,
+            export_dir: output_dir_setting.to_path_buf(),
+        }
+    }
+
+    /// 按照报告`report`中记录的设置，只重新处理其中失败的图片
+    pub fn retry(config: &Config, report: &JobReport) -> anyhow::Result<Self> {
+        let failed_pages: HashSet<PathBuf> = report
+            .pages
+            .iter()
+            .filter(|page| page.outcome == PageOutcome::Failed)
+            .map(|page| page.img_path.clone())
+            .collect();
+        if failed_pages.is_empty() {
+            return Err(anyhow!(messages::no_failed_pages(&report.id)));
+        }
+        let mut options = Self::new(
+            config,
+            &report.manga_dir,
+            &report.output_dir,
+            report.format.clone(),
+            report.optimize,
+            report.comic_info,
+            report.conflict_policy,
+        );
+        // 报告中的输出目录已经是原地处理后的结果，不需要根据当前的设置重新计算
+        options.output_dir = report.output_dir.clone();
+        options.conflict_policy = report.conflict_policy;
+        options.layout = report.layout;
+        options.template = report.template.clone();
+        options.incremental = report.incremental;
+        options.in_place = report.in_place;
+        options.export_dir = config.output_dir.clone();
+        options.only_pages = Some(failed_pages);
+        Ok(options)
+    }
+
+    /// 漫画目录的名字和父目录
+    fn manga_name_and_parent(&self) -> anyhow::Result<(&str, &Path)> {
+        let manga_dir = &self.manga_dir;
+        let manga_dir_without_name = manga_dir
+            .parent()
+            .ok_or(anyhow!(messages::manga_parent_missing(manga_dir)))?;
+        let manga_name = manga_dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(anyhow!(messages::manga_name_failed(manga_dir)))?;
+        Ok((manga_name, manga_dir_without_name))
+    }
+
+    /// 去水印后的漫画实际所在的目录，原地处理时就是漫画目录
+    pub fn output_root(&self) -> anyhow::Result<PathBuf> {
+        if self.in_place {
+            return Ok(self.manga_dir.clone());
+        }
+        let (manga_name, _) = self.manga_name_and_parent()?;
+        let output_root = output_template::output_root(self.layout, &self.template, manga_name);
+        Ok(self.output_dir.join(output_root))
+    }
+
+    /// 去除水印时的设置，记录在清单中，设置改变后需要重新处理
+    fn settings(&self) -> String {
+        format!("{:?} optimize={}", self.format, self.optimize)
+    }
+}
+
+/// 一次去水印的结果
+pub struct RemoveWatermarkOutcome {
+    pub plan: RemoveWatermarkPlan,
+    pub report: JobReport,
+}
+
+impl RemoveWatermarkOutcome {
+    /// 开启了出错后继续时处理失败的图片
+    pub fn failures(&self) -> Vec<PageFailure> {
+        self.report
+            .pages
+            .iter()
+            .filter_map(|page| {
+                let err_msg = page.error.clone()?;
+                Some(PageFailure {
+                    dir_path: page.dir_path.clone(),
+                    img_path: page.img_path.clone(),
+                    err_msg,
+                })
+            })
+            .collect()
+    }
+}
+
+/// 按照`options`去除漫画目录中图片的水印，先规划再执行，返回执行的规划和报告，报告也会保存到`JobHistory`中
+pub fn remove_watermark(
+    app: &AppHandle,
+    options: &RemoveWatermarkOptions,
+    backgrounds: &Backgrounds,
+    scan_cache: &ScanCache,
+) -> anyhow::Result<RemoveWatermarkOutcome> {
+    tracing::info!(
+        manga_dir = ?options.manga_dir,
+        output_dir = ?options.output_dir,
+        settings = options.settings(),
+        only_pages = options.only_pages.as_ref().map(HashSet::len),
+        "{}", messages::log_removal_started()
+    );
+    let resources = app.state::<ProcessingResources>();
+    // 规划中的par_iter和流水线中解码、去水印、编码的计算都在这个共享的线程池中执行
+    let pool = resources.pool(options.worker_threads)?;
+    let outcome = remove_watermark_with(
+        app,
+        options,
+        backgrounds,
+        scan_cache,
+        &pool,
+        resources.budget(),
+    )?;
+    let report = &outcome.report;
+    tracing::info!(
+        manga_dir = ?report.manga_dir,
+        report_id = report.id,
+        removed = report.removed,
+        copied = report.copied,
+        skipped = report.skipped,
+        failed = report.failed,
+        elapsed_secs = report.elapsed_secs,
+        "{}", messages::log_removal_finished()
+    );
+    app.state::<JobHistory>().push(outcome.report.clone());
+    Ok(outcome)
+}
+
+/// 不能整个在`pool.install`中执行，否则线程池中的线程会阻塞在等待流水线结束上，线程池只有一个线程时就无法继续
+fn remove_watermark_with(
+    app: &AppHandle,
+    options: &RemoveWatermarkOptions,
+    backgrounds: &Backgrounds,
+    scan_cache: &ScanCache,
+    pool: &ThreadPool,
+    budget: &MemoryBudget,
+) -> anyhow::Result<RemoveWatermarkOutcome> {
+    let started_at = chrono::Local::now();
+    let started = Instant::now();
+    // 这部漫画的清单，记录每张原图片的处理情况，用于跳过没有变化的图片
+    let manifest = Manifest::load(&options.manga_dir, &options.output_dir, options.in_place);
+    // 原地处理时，原图片会被移动到备份中，输出直接写回漫画目录
+    let backup = if options.in_place {
+        Some(Backup::open(&options.manga_dir, options.backup_mode)?)
+    } else {
+        None
+    };
+    // 先规划每张图片如何处理，再按照规划执行，与plan_remove_watermark的结果一致
+    let plan =
+        pool.install(|| plan(options, backgrounds, scan_cache, &manifest, backup.as_ref()))?;
+    let pages = execute(
+        app,
+        options,
+        backgrounds,
+        &plan,
+        &manifest,
+        backup.as_ref(),
+        pool,
+        budget,
+    );
+    // 出错时也要保存备份的索引，否则已经移动到备份中的原图片无法恢复
+    if let Some(backup) = &backup {
+        backup.finish()?;
+    }
+    let pages = pages?;
+    let output_root = options.output_root()?;
+    let (exported, export_error) =
+        pool.install(|| export_output(options, &output_root, scan_cache));
+    scan_cache.save()?;
+
+    let count = |outcome| {
+        let count = pages.iter().filter(|page| page.outcome == outcome).count();
+        u32::try_from(count).unwrap_or(u32::MAX)
+    };
+    let report = JobReport {
+        id: uuid::Uuid::new_v4().to_string(),
+        manga_dir: options.manga_dir.clone(),
+        output_dir: options.output_dir.clone(),
+        format: options.format.clone(),
+        optimize: options.optimize,
+        comic_info: options.comic_info,
+        conflict_policy: options.conflict_policy,
+        layout: options.layout,
+        template: options.template.clone(),
+        in_place: options.in_place,
+        incremental: options.incremental,
+        backgrounds: backgrounds.report(),
+        started_at: started_at.to_rfc3339(),
+        finished_at: chrono::Local::now().to_rfc3339(),
+        elapsed_secs: started.elapsed().as_secs_f64(),
+        removed: count(PageOutcome::Removed),
+        copied: count(PageOutcome::Copied),
+        skipped: count(PageOutcome::Skipped),
+        failed: count(PageOutcome::Failed),
+        pages,
+        output_root,
+        exported,
+        export_error,
+    };
+    Ok(RemoveWatermarkOutcome { plan, report })
+}
+
+/// 设置了导出格式时，导出去水印后的漫画`output_root`，返回(导出的文件, 导出失败时的错误信息)
+///
+/// 导出失败不影响去水印的结果，错误记录在报告中
+fn export_output(
+    options: &RemoveWatermarkOptions,
+    output_root: &Path,
+    scan_cache: &ScanCache,
+) -> (Vec<PathBuf>, Option<String>) {
+    let Some(format) = options.export_format else {
+        return (vec![], None);
+    };
+    let result = options.manga_name_and_parent().and_then(|(manga_name, _)| {
+        export::export_manga(
+            output_root,
+            manga_name,
+            &options.export_dir,
+            format,
+            options.export_scope,
+            &options.scan_options,
+            scan_cache,
+        )
+    });
+    match result {
+        Ok(exported) => {
+            tracing::info!(
+                output_root = ?output_root,
+                files = exported.len(),
+                "{}", messages::log_exported()
+            );
+            (exported, None)
+        }
+        Err(err) => {
+            let err_msg = err.to_string_chain();
+            tracing::warn!(output_root = ?output_root, err_msg, "{}", messages::log_export_failed());
+            (vec![], Some(err_msg))
+        }
+    }
+}
+
+/// 按尺寸索引的黑色背景和白色背景水印图
+pub struct Backgrounds {
+    // (width, height) => (black, white)
+    images: HashMap<(u32, u32), (RgbImage, RgbImage)>,
+    // (width, height) => 背景水印图的版本
+    versions: HashMap<(u32, u32), String>,
+}
+
+impl Backgrounds {
+    pub fn new(backgrounds_data: &[(ImageData, ImageData)]) -> anyhow::Result<Self> {
+        let images = create_backgrounds(backgrounds_data)?;
+        Ok(Self::from_images(images))
+    }
+
+    /// 从磁盘读取背景水印图，`background_paths`中的每个元素是(黑色背景水印图路径, 白色背景水印图路径)
+    pub fn open(background_paths: &[(PathBuf, PathBuf)]) -> anyhow::Result<Self> {
+        let images = background_paths
+            .iter()
+            .map(|(black_path, white_path)| {
+                let black = utils::decode_image(black_path)
+                    .context(messages::read_black_background_failed(black_path))?
+                    .to_rgb8();
+                let white = utils::decode_image(white_path)
+                    .context(messages::read_white_background_failed(white_path))?
+                    .to_rgb8();
+                check_background_dimensions(&black, &white)?;
+                Ok(((black.width(), black.height()), (black, white)))
+            })
+            .collect::<anyhow::Result<HashMap<(u32, u32), (RgbImage, RgbImage)>>>()?;
+        Ok(Self::from_images(images))
+    }
+
+    /// 从背景水印图目录读取报告`report`中使用的背景水印图，报告之后背景水印图被修改过时返回错误
+    pub fn open_for_report(app: &AppHandle, report: &JobReport) -> anyhow::Result<Self> {
+        let manga_dir = report
+            .manga_dir
+            .to_str()
+            .ok_or(anyhow!(messages::manga_dir_non_utf8(&report.manga_dir)))?;
+        let background_paths = report
+            .backgrounds
+            .iter()
+            .map(|background| {
+                let background_dir = utils::get_background_dir_abs_path(
+                    app,
+                    manga_dir,
+                    background.width,
+                    background.height,
+                )?;
+                let black_path = background_dir.join("black.png");
+                let white_path = background_dir.join("white.png");
+                if !black_path.exists() || !white_path.exists() {
+                    let (width, height) = (background.width, background.height);
+                    return Err(ErrorKind::BackgroundMissing { width, height }.error(
+                        messages::background_dir_missing(width, height, &background_dir),
+                    ));
+                }
+                Ok((black_path, white_path))
+            })
+            .collect::<anyhow::Result<Vec<(PathBuf, PathBuf)>>>()?;
+        let backgrounds = Self::open(&background_paths)?;
+        for background in &report.backgrounds {
+            let (width, height) = (background.width, background.height);
+            if backgrounds.version(width, height) != Some(background.version.as_str()) {
+                return Err(anyhow!(messages::background_changed(width, height)));
+            }
+        }
+        Ok(backgrounds)
+    }
+
+    fn from_images(images: HashMap<(u32, u32), (RgbImage, RgbImage)>) -> Self {
+        let versions = images
+            .iter()
+            .map(|(&size, (black, white))| {
+                let version = manifest::background_version(black.as_raw(), white.as_raw());
+                (size, version)
+            })
+            .collect();
+        Self { images, versions }
+    }
+
+    pub fn get(&self, width: u32, height: u32) -> Option<&(RgbImage, RgbImage)> {
+        self.images.get(&(width, height))
+    }
+
+    pub fn version(&self, width: u32, height: u32) -> Option<&str> {
+        self.versions.get(&(width, height)).map(String::as_str)
+    }
+
+    /// 所有背景水印图的尺寸和版本，按尺寸排序，用于报告
+    pub fn report(&self) -> Vec<ReportBackground> {
+        let mut backgrounds: Vec<ReportBackground> = self
+            .versions
+            .iter()
+            .map(|(&(width, height), version)| ReportBackground {
+                width,
+                height,
+                version: version.clone(),
+            })
+            .collect();
+        backgrounds.sort_by_key(|background| (background.width, background.height));
+        backgrounds
+    }
+}
+
+/// 构建一个`HashMap`，`key`是背景水印图的尺寸，`value`是黑色背景和白色背景水印图
+fn create_backgrounds(
+    backgrounds_data: &[(ImageData, ImageData)],
+) -> anyhow::Result<HashMap<(u32, u32), (RgbImage, RgbImage)>> {
+    let backgrounds = backgrounds_data
+        .iter()
+        .map(|(black_data, white_data)| {
+            let black = black_data
+                .to_image()
+                .context(messages::convert_black_background_failed(
+                    &black_data.info.path,
+                ))?
+                .to_rgb8();
+            let white = white_data
+                .to_image()
+                .context(messages::convert_white_background_failed(
+                    &white_data.info.path,
+                ))?
+                .to_rgb8();
+            check_background_dimensions(&black, &white)?;
+            Ok(((black.width(), black.height()), (black, white)))
+        })
+        .collect::<anyhow::Result<HashMap<(u32, u32), (RgbImage, RgbImage)>>>()?;
+    Ok(backgrounds)
+}
+
+fn check_background_dimensions(black: &RgbImage, white: &RgbImage) -> anyhow::Result<()> {
+    if black.dimensions() != white.dimensions() {
+        let kind = ErrorKind::SizeMismatch {
+            width: white.width(),
+            height: white.height(),
+            expected_width: black.width(),
+            expected_height: black.height(),
+        };
+        return Err(kind.error(messages::background_size_mismatch(
+            black.width(),
+            black.height(),
+            white.width(),
+            white.height(),
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,463 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use crossbeam_channel::{Receiver, Sender};
+use image::RgbImage;
+use rayon::ThreadPool;
+
+use crate::backup::Backup;
+use crate::errors::{ErrorKind, ResultKindContext};
+use crate::manifest;
+use crate::messages;
+use crate::remover::image_ops::{encode_image, remove_image_watermark, with_output_extension};
+use crate::remover::resources::{MemoryBudget, MemoryPermit};
+use crate::remover::{Backgrounds, RemoveWatermarkOptions};
+use crate::types::{PageAction, PagePlan, PipelineStage, StageStats};
+use crate::utils;
+
+/// 流水线的阶段，按图片经过的顺序排列
+const STAGES: [PipelineStage; 5] = [
+    PipelineStage::Reader,
+    PipelineStage::Decoder,
+    PipelineStage::Kernel,
+    PipelineStage::Encoder,
+    PipelineStage::Writer,
+];
+/// 读取阶段的默认线程数，读取主要是在等待磁盘，和CPU核心数无关
+const DEFAULT_READER_THREADS: usize = 4;
+/// 写入阶段的默认线程数
+const DEFAULT_WRITER_THREADS: usize = 2;
+/// 每个阶段的输入通道能容纳的图片数是该阶段线程数的倍数，上游最多领先这么多张图片
+const CHANNEL_CAPACITY_PER_THREAD: usize = 2;
+
+/// 图片在流水线中的数据，每经过一个阶段就变为下一种数据
+enum PageData<'a> {
+    // 还没读取
+    Unread,
+    // 跳过的图片，不需要读取和写入
+    Skip,
+    // 从磁盘读取的原图片数据，不需要去水印的图片会原样写入
+    Raw(Vec<u8>),
+    // 解码后的图片，持有的内存预算在编码后释放
+    Decoded(RgbImage, MemoryPermit<'a>),
+    // 编码后的输出图片数据
+    Encoded(Vec<u8>),
+    // 开启了出错后继续时，处理失败的图片，之后的阶段都会直接跳过它
+    Failed(anyhow::Error),
+}
+
+struct PageWork<'a> {
+    chapter_index: usize,
+    page: &'a PagePlan,
+    // 实际读取的原图片，原地处理时是备份中的原图片
+    source_path: PathBuf,
+    // 原图片内容的哈希，读取后才有
+    content_hash: Option<String>,
+    // 读取的原图片字节数
+    read_bytes: u64,
+    // 写入的输出图片字节数
+    written_bytes: u64,
+    // 开始读取的时间，跳过的图片为`None`
+    started: Option<Instant>,
+    // 需要去水印时才有背景水印图
+    background: Option<&'a (RgbImage, RgbImage)>,
+    data: PageData<'a>,
+}
+
+/// 写入完成(或跳过)的图片
+pub struct WrittenPage<'a> {
+    pub chapter_index: usize,
+    pub page: &'a PagePlan,
+    pub source_path: PathBuf,
+    pub content_hash: Option<String>,
+    // 是否去除了水印，为false时是原样复制或跳过
+    pub watermark_removed: bool,
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+    // 从开始读取到写入完成花费的时间，跳过的图片为0
+    pub duration: Duration,
+    // 开启了出错后继续时，处理这张图片的错误
+    pub error: Option<anyhow::Error>,
+}
+
+/// 一个阶段的统计数据，所有线程共享
+#[derive(Default)]
+struct StageCounter {
+    items: AtomicU32,
+    bytes: AtomicU64,
+    busy_nanos: AtomicU64,
+}
+
+impl StageCounter {
+    #[allow(clippy::cast_possible_truncation)]
+    fn record(&self, bytes: u64, started: Instant) {
+        self.items.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        let busy_nanos = started.elapsed().as_nanos() as u64;
+        self.busy_nanos.fetch_add(busy_nanos, Ordering::Relaxed);
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    fn stats(&self, stage: PipelineStage, threads: usize, elapsed: Duration) -> StageStats {
+        let items = self.items.load(Ordering::Relaxed);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let busy_nanos = self.busy_nanos.load(Ordering::Relaxed);
+        let elapsed_secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        StageStats {
+            stage,
+            threads: threads as u32,
+            items,
+            bytes,
+            busy_secs: Duration::from_nanos(busy_nanos).as_secs_f64(),
+            items_per_sec: f64::from(items) / elapsed_secs,
+            mb_per_sec: bytes as f64 / 1024.0 / 1024.0 / elapsed_secs,
+        }
+    }
+}
+
+/// 把去水印拆分为读取、解码、去水印、编码、写入五个阶段，阶段之间用有界通道连接，
+/// 每个阶段有自己的线程，这样读写磁盘和占用CPU的阶段可以同时进行，下游处理不过来时上游会被阻塞
+///
+/// 解码、去水印、编码阶段的线程只负责调度，实际的计算提交到`pool`中执行，
+/// 所以无论每个阶段有多少线程、同时执行多少个任务，占用CPU的线程数都不会超过`pool`的线程数
+pub struct Pipeline<'a> {
+    pub options: &'a RemoveWatermarkOptions,
+    pub backgrounds: &'a Backgrounds,
+    pub backup: Option<&'a Backup>,
+    pub pool: &'a ThreadPool,
+    pub budget: &'a MemoryBudget,
+    // 每张图片写入(或跳过)后在写入线程中调用
+    pub on_written: &'a (dyn Fn(WrittenPage<'a>) -> anyhow::Result<()> + Sync),
+}
+
+impl<'a> Pipeline<'a> {
+    /// 处理`pages`中的所有图片，`pages`中的元素是(章节的索引, 图片)，返回每个阶段的吞吐量
+    ///
+    /// 任何一张图片出错时，所有阶段都会停止，返回第一个错误；开启了出错后继续时，失败的图片通过`on_written`报告
+    pub fn run(&self, pages: Vec<(usize, &'a PagePlan)>) -> anyhow::Result<Vec<StageStats>> {
+        let started = Instant::now();
+        let cancelled = AtomicBool::new(false);
+        let counters: [StageCounter; STAGES.len()] = Default::default();
+        // 所有图片一开始就放进读取阶段的通道，读取阶段自己决定读多快
+        let (page_sender, page_receiver) = crossbeam_channel::unbounded();
+        for (chapter_index, page) in pages {
+            let data = match page.action {
+                PageAction::Skip => PageData::Skip,
+                PageAction::RemoveWatermark | PageAction::Copy => PageData::Unread,
+                // 规划时就失败的图片直接报告
+                PageAction::Fail => {
+                    PageData::Failed(anyhow!(page.error.clone().unwrap_or_default()))
+                }
+            };
+            let work = PageWork {
+                chapter_index,
+                page,
+                source_path: page.source_path.clone(),
+                content_hash: None,
+                read_bytes: 0,
+                written_bytes: 0,
+                started: None,
+                background: None,
+                data,
+            };
+            // 只有读取阶段的通道已经关闭时才会失败
+            page_sender
+                .send(work)
+                .map_err(|_| anyhow!(messages::send_to_reader_failed()))?;
+        }
+        drop(page_sender);
+
+        std::thread::scope(|scope| {
+            let mut handles = Vec::new();
+            let mut input = page_receiver;
+            for (i, (&stage, counter)) in STAGES.iter().zip(&counters).enumerate() {
+                let (output, next_input) = match STAGES.get(i + 1) {
+                    Some(&next_stage) => {
+                        let capacity = self.threads(next_stage) * CHANNEL_CAPACITY_PER_THREAD;
+                        let (sender, receiver) = crossbeam_channel::bounded(capacity);
+                        (Some(sender), Some(receiver))
+                    }
+                    None => (None, None),
+                };
+                for _ in 0..self.threads(stage) {
+                    let input = input.clone();
+                    let output = output.clone();
+                    let cancelled = &cancelled;
+                    handles.push(scope.spawn(move || {
+                        self.run_stage(stage, &input, output.as_ref(), cancelled, counter)
+                    }));
+                }
+                // 只有所有线程都持有通道的一端，线程全部退出后通道才会关闭，下游才能结束
+                drop(output);
+                let Some(next_input) = next_input else {
+                    drop(input);
+                    break;
+                };
+                input = next_input;
+            }
+
+            let mut result = Ok(());
+            for handle in handles {
+                let thread_result = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!(messages::pipeline_thread_panicked())));
+                // 只保留第一个错误，其他线程都是因为取消而退出的
+                if result.is_ok() {
+                    result = thread_result;
+                }
+            }
+            result
+        })?;
+
+        let elapsed = started.elapsed();
+        let stage_stats = STAGES
+            .iter()
+            .zip(&counters)
+            .map(|(&stage, counter)| counter.stats(stage, self.threads(stage), elapsed))
+            .collect();
+        Ok(stage_stats)
+    }
+
+    /// 阶段`stage`的线程数，没有设置时使用默认值
+    fn threads(&self, stage: PipelineStage) -> usize {
+        let pipeline_threads = &self.options.pipeline_threads;
+        let configured = match stage {
+            PipelineStage::Reader => pipeline_threads.reader,
+            PipelineStage::Decoder => pipeline_threads.decoder,
+            PipelineStage::Kernel => pipeline_threads.kernel,
+            PipelineStage::Encoder => pipeline_threads.encoder,
+            PipelineStage::Writer => pipeline_threads.writer,
+        } as usize;
+        if configured != 0 {
+            return configured;
+        }
+        match stage {
+            PipelineStage::Reader => DEFAULT_READER_THREADS,
+            PipelineStage::Writer => DEFAULT_WRITER_THREADS,
+            // 与线程池的线程数相同，保证每个阶段都能让线程池满载
+            PipelineStage::Decoder | PipelineStage::Kernel | PipelineStage::Encoder => {
+                self.pool.current_num_threads()
+            }
+        }
+    }
+
+    /// 阶段`stage`的一个线程，不断从`input`取出图片处理后发送到`output`，直到上游结束
+    ///
+    /// 被取消后不会立即退出，而是继续取出上游的图片直接丢弃，这样通道中的图片持有的内存预算会被释放，
+    /// 上游也不会阻塞在发送上，所有阶段都能依次结束
+    fn run_stage(
+        &self,
+        stage: PipelineStage,
+        input: &Receiver<PageWork<'a>>,
+        output: Option<&Sender<PageWork<'a>>>,
+        cancelled: &AtomicBool,
+        counter: &StageCounter,
+    ) -> anyhow::Result<()> {
+        let mut stage_result = Ok(());
+        for mut work in input {
+            if cancelled.load(Ordering::Relaxed) {
+                continue;
+            }
+            let started = Instant::now();
+            let result = match stage {
+                PipelineStage::Reader => self.read(&mut work),
+                PipelineStage::Decoder => self.decode(&mut work, cancelled),
+                PipelineStage::Kernel => Ok(self.remove_watermark(&mut work)),
+                PipelineStage::Encoder => self.encode(&mut work),
+                PipelineStage::Writer => self.write(&mut work),
+            };
+            match result {
+                Ok(Some(bytes)) => counter.record(bytes, started),
+                Ok(None) => {}
+                // 只有这张图片失败，之后的阶段会跳过它，最后在写入阶段报告
+                Err(err) if self.options.continue_on_error => work.data = PageData::Failed(err),
+                Err(err) => {
+                    self.cancel(cancelled);
+                    stage_result = Err(err);
+                    continue;
+                }
+            }
+            if stage == PipelineStage::Writer {
+                // `on_written`的错误与具体的图片无关，总是停止所有阶段
+                if let Err(err) = self.finish(work) {
+                    self.cancel(cancelled);
+                    stage_result = Err(err);
+                }
+            } else if let Some(output) = output {
+                // 解码时可能因为取消而没有完成，这样的图片不再发送到下游
+                if cancelled.load(Ordering::Relaxed) {
+                    continue;
+                }
+                // 下游的线程都已经退出，说明已经被取消
+                if output.send(work).is_err() {
+                    break;
+                }
+            }
+        }
+        stage_result
+    }
+
+    /// 取消所有阶段，并唤醒等待内存预算的解码线程
+    fn cancel(&self, cancelled: &AtomicBool) {
+        cancelled.store(true, Ordering::Relaxed);
+        self.budget.wake_all();
+    }
+
+    /// 读取原图片，返回读取的字节数，跳过的图片返回`None`
+    fn read(&self, work: &mut PageWork<'a>) -> anyhow::Result<Option<u64>> {
+        if !matches!(work.data, PageData::Unread) {
+            return Ok(None);
+        }
+        let page = work.page;
+        work.started = Some(Instant::now());
+        // 原地处理时先备份原图片，之后总是从备份中读取原图片
+        if let Some(backup) = self.backup {
+            work.source_path = backup.backup_original(&page.img_path)?;
+        }
+        let source_path = &work.source_path;
+        let data = std::fs::read(source_path).kind_context(
+            ErrorKind::io(source_path),
+            messages::read_image_failed(source_path),
+        )?;
+        work.content_hash = Some(manifest::content_hash_of(&data));
+        work.background = match page.action {
+            PageAction::RemoveWatermark => self.backgrounds.get(page.width, page.height),
+            PageAction::Copy | PageAction::Skip | PageAction::Fail => None,
+        };
+        work.read_bytes = data.len() as u64;
+        work.data = PageData::Raw(data);
+        Ok(Some(work.read_bytes))
+    }
+
+    /// 解码需要去水印的图片，在内存预算允许时才开始解码，返回解码后像素数据的字节数，等待内存预算时被取消则不解码
+    fn decode(
+        &self,
+        work: &mut PageWork<'a>,
+        cancelled: &AtomicBool,
+    ) -> anyhow::Result<Option<u64>> {
+        let (Some(_), PageData::Raw(data)) = (work.background, &work.data) else {
+            return Ok(None);
+        };
+        let page = work.page;
+        let Some(permit) = self.budget.acquire(
+            page.width,
+            page.height,
+            self.options.memory_budget,
+            cancelled,
+        ) else {
+            return Ok(None);
+        };
+        let source_path = &work.source_path;
+        let img = self
+            .pool
+            .install(|| utils::decode_image_bytes(data, source_path))?
+            .into_rgb8();
+        let bytes = img.as_raw().len() as u64;
+        work.data = PageData::Decoded(img, permit);
+        Ok(Some(bytes))
+    }
+
+    /// 去除解码后的图片的水印，返回像素数据的字节数
+    fn remove_watermark(&self, work: &mut PageWork<'a>) -> Option<u64> {
+        let (Some((black, white)), PageData::Decoded(img, _)) = (work.background, &mut work.data)
+        else {
+            return None;
+        };
+        self.pool
+            .install(|| remove_image_watermark(black, white, img));
+        Some(img.as_raw().len() as u64)
+    }
+
+    /// 编码去除水印后的图片，编码后释放图片占用的内存预算，返回编码后的字节数
+    fn encode(&self, work: &mut PageWork<'a>) -> anyhow::Result<Option<u64>> {
+        let PageData::Decoded(img, _) = &work.data else {
+            return Ok(None);
+        };
+        let out_image_path = &work.page.out_image_path;
+        let data = self
+            .pool
+            .install(|| encode_image(img, &self.options.format, self.options.optimize))
+            .kind_context(
+                ErrorKind::encode(out_image_path),
+                messages::encode_image_failed(out_image_path),
+            )?;
+        let bytes = data.len() as u64;
+        work.data = PageData::Encoded(data);
+        Ok(Some(bytes))
+    }
+
+    /// 写入输出图片，返回写入的字节数，跳过和失败的图片返回`None`
+    fn write(&self, work: &mut PageWork<'a>) -> anyhow::Result<Option<u64>> {
+        let page = work.page;
+        let bytes = match &work.data {
+            // 去除了水印的图片
+            PageData::Encoded(data) => {
+                let out_image_path =
+                    with_output_extension(&page.out_image_path, &self.options.format);
+                write_file(&out_image_path, data)?;
+                data.len() as u64
+            }
+            // 不需要去水印的图片，直接复制原图片
+            PageData::Raw(data) => {
+                write_file(&page.out_image_path, data)?;
+                data.len() as u64
+            }
+            PageData::Skip | PageData::Failed(_) => return Ok(None),
+            PageData::Unread | PageData::Decoded(..) => {
+                return Err(anyhow!(messages::page_skipped_stages(&page.img_path)));
+            }
+        };
+        work.written_bytes = bytes;
+        Ok(Some(bytes))
+    }
+
+    /// 图片写入(或跳过、失败)后调用`on_written`
+    fn finish(&self, work: PageWork<'a>) -> anyhow::Result<()> {
+        let error = match work.data {
+            PageData::Failed(err) => Some(err),
+            _ => None,
+        };
+        let written = WrittenPage {
+            chapter_index: work.chapter_index,
+            page: work.page,
+            source_path: work.source_path,
+            content_hash: work.content_hash,
+            watermark_removed: work.background.is_some(),
+            read_bytes: work.read_bytes,
+            written_bytes: work.written_bytes,
+            duration: work
+                .started
+                .map(|started| started.elapsed())
+                .unwrap_or_default(),
+            error,
+        };
+        (self.on_written)(written)
+    }
+}
+
+/// 将`data`写入`path`，保证目录存在
+fn write_file(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .kind_context(ErrorKind::io(parent), messages::create_dir_failed(parent))?;
+    }
+    // 先写入同一目录中的临时文件再重命名，中途出错或被终止时不会留下不完整的图片，原地处理时也不会损坏原图片
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let result = std::fs::write(&tmp_path, data)
+        .kind_context(ErrorKind::io(path), messages::save_image_failed(path))
+        .and_then(|()| {
+            std::fs::rename(&tmp_path, path).kind_context(
+                ErrorKind::io(path),
+                messages::rename_failed(&tmp_path, path),
+            )
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
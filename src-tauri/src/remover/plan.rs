@@ -0,0 +1,359 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context};
+use parking_lot::Mutex;
+use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use crate::backup::Backup;
+use crate::errors::ErrorKind;
+use crate::extensions::AnyhowErrorToStringChain;
+use crate::manifest::{Manifest, SourceStamp};
+use crate::messages;
+use crate::output_template::{self, TemplateVars};
+use crate::remover::{Backgrounds, RemoveWatermarkOptions};
+use crate::scan_cache::ScanCache;
+use crate::scanner;
+use crate::types::{
+    ChapterPlan, ConflictPolicy, ConflictResolution, ImageFormat, OutputLayout, PageAction,
+    PagePlan, RemoveWatermarkPlan, SizeCount,
+};
+
+/// 估算去水印后每个像素编码后的字节数，只用于预估输出大小
+const JPEG_BYTES_PER_PIXEL: f64 = 0.35;
+const PNG_BYTES_PER_PIXEL: f64 = 1.2;
+
+/// 规划过程中所有图片共享的数据
+struct PlanContext<'a> {
+    options: &'a RemoveWatermarkOptions,
+    backgrounds: &'a Backgrounds,
+    scan_cache: &'a ScanCache,
+    manifest: &'a Manifest,
+    backup: Option<&'a Backup>,
+    manga_name: &'a str,
+    manga_dir_without_name: &'a Path,
+    // 已经使用过的输出路径，用于检查模板是否会让不同的图片输出到同一个路径
+    used_out_paths: Mutex<HashSet<PathBuf>>,
+}
+
+/// 规划每张图片会被如何处理，不会解码图片，也不会修改任何文件
+///
+/// 执行去水印时也是先规划再按照规划执行，所以规划的结果与实际执行的结果一致
+#[allow(clippy::cast_possible_truncation)]
+pub fn plan(
+    options: &RemoveWatermarkOptions,
+    backgrounds: &Backgrounds,
+    scan_cache: &ScanCache,
+    manifest: &Manifest,
+    backup: Option<&Backup>,
+) -> anyhow::Result<RemoveWatermarkPlan> {
+    let (manga_name, manga_dir_without_name) = options.manga_name_and_parent()?;
+    if options.layout == OutputLayout::Template {
+        output_template::validate(&options.template)?;
+    }
+    // 与统计尺寸时使用相同的扫描规则，保证处理的图片与统计结果一致
+    let manga_tree =
+        scanner::scan_manga_dir(&options.manga_dir, &options.scan_options, scan_cache)?;
+    let ctx = PlanContext {
+        options,
+        backgrounds,
+        scan_cache,
+        manifest,
+        backup,
+        manga_name,
+        manga_dir_without_name,
+        used_out_paths: Mutex::new(HashSet::new()),
+    };
+
+    let mut chapters = vec![];
+    for (chapter_index, chapter) in manga_tree.chapters.into_iter().enumerate() {
+        let dir = chapter.dir;
+        let chapter_name = dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(anyhow!(messages::chapter_name_failed(&dir)))?
+            .to_string();
+        // 使用rayon的并行迭代器，并行规划每个目录下的图片，结果的顺序与图片的顺序一致
+        let pages = chapter
+            .pages
+            .par_iter()
+            .enumerate()
+            .filter(|(_, img_path)| match &options.only_pages {
+                Some(only_pages) => only_pages.contains(*img_path),
+                None => true,
+            })
+            .map(|(page_index, img_path)| {
+                plan_page(&ctx, chapter_index, &chapter_name, page_index, img_path)
+            })
+            .collect::<anyhow::Result<Vec<PagePlan>>>()?;
+        // 只处理部分图片时，跳过没有需要处理的图片的章节
+        if pages.is_empty() && options.only_pages.is_some() {
+            continue;
+        }
+        chapters.push(ChapterPlan {
+            dir,
+            name: chapter_name,
+            pages,
+            page_count: chapter.pages.len() as u32,
+        });
+    }
+
+    Ok(summarize(options, backgrounds, chapters))
+}
+
+fn plan_page(
+    ctx: &PlanContext,
+    chapter_index: usize,
+    chapter_name: &str,
+    page_index: usize,
+    img_path: &Path,
+) -> anyhow::Result<PagePlan> {
+    let options = ctx.options;
+    // 原地处理时总是从备份中读取原图片，还没备份时原图片还在img_path
+    let source_path = match ctx.backup {
+        Some(backup) => backup.original_path(img_path)?,
+        None => img_path.to_path_buf(),
+    };
+    let source_exists = source_path.exists();
+    let current_source = if source_exists {
+        source_path.as_path()
+    } else {
+        img_path
+    };
+    // 获取图片的尺寸，按后缀识别的图片可能是空文件或损坏的文件
+    let info = match ctx.scan_cache.probe(current_source) {
+        Ok(info) => info,
+        // 开启了出错后继续时只让这张图片失败，不影响其他图片
+        Err(err) if options.continue_on_error => {
+            return Ok(PagePlan {
+                img_path: img_path.to_path_buf(),
+                source_path,
+                target_path: PathBuf::new(),
+                out_image_path: PathBuf::new(),
+                width: 0,
+                height: 0,
+                action: PageAction::Fail,
+                conflict_resolution: ConflictResolution::NoConflict,
+                estimated_bytes: 0,
+                error: Some(err.to_string_chain()),
+            })
+        }
+        Err(err) => return Err(err),
+    };
+    let (width, height) = (info.width, info.height);
+    let background = ctx.backgrounds.get(width, height);
+    // 去除水印的图片使用输出格式的后缀，直接复制的图片使用其真实格式的后缀
+    let ext = match (background, &options.format) {
+        (Some(_), ImageFormat::Jpeg) => "jpg",
+        (Some(_), ImageFormat::Png) => "png",
+        (None, _) => info.format.extension(),
+    };
+
+    let stem = output_template::image_stem(img_path)
+        .ok_or(anyhow!(messages::image_name_failed(img_path)))?;
+    let target_path =
+        if options.in_place {
+            // 原地处理时输出到原图片的位置，只有后缀可能改变
+            img_path.with_file_name(format!("{stem}.{ext}"))
+        } else {
+            // 获取相对路径(漫画名/章节名/图片名)
+            let relative_path = img_path.strip_prefix(ctx.manga_dir_without_name).context(
+                messages::not_parent_dir(ctx.manga_dir_without_name, img_path),
+            )?;
+            let vars = TemplateVars {
+                manga: ctx.manga_name,
+                chapter: chapter_name,
+                chapter_index: chapter_index + 1,
+                page: page_index + 1,
+                stem,
+                ext,
+            };
+            // 构建输出图片的路径(默认为 输出目录/漫画名/章节名/图片名)
+            let out_relative_path = output_template::output_relative_path(
+                options.layout,
+                &options.template,
+                relative_path,
+                &vars,
+            )?;
+            options.output_dir.join(out_relative_path)
+        };
+    if !ctx.used_out_paths.lock().insert(target_path.clone()) {
+        return Err(ErrorKind::Config.error(messages::duplicate_output_path(&target_path)));
+    }
+
+    // 原地处理时，只有原图片已经在备份中才可能没有变化(恢复原图后需要重新处理)
+    let unchanged_output = if options.incremental && source_exists {
+        let settings = match background {
+            Some(_) => options.settings(),
+            None => "copy".to_string(),
+        };
+        let stamp = SourceStamp::read(&source_path)?;
+        ctx.manifest.unchanged_output(
+            &source_path,
+            &stamp,
+            &target_path,
+            &settings,
+            ctx.backgrounds.version(width, height),
+        )?
+    } else {
+        None
+    };
+    let (out_image_path, conflict_resolution) = match unchanged_output {
+        // 原图片和设置都没有变化，沿用上次的输出
+        Some(output_path) => (output_path, ConflictResolution::Unchanged),
+        // 输出路径已存在文件时，根据conflict_policy决定如何处理
+        None => resolve_conflict(
+            target_path.clone(),
+            current_source,
+            options.conflict_policy,
+            &ctx.used_out_paths,
+        )?,
+    };
+
+    let action = match (conflict_resolution, background) {
+        (ConflictResolution::Skipped | ConflictResolution::Unchanged, _) => PageAction::Skip,
+        (_, Some(_)) => PageAction::RemoveWatermark,
+        (_, None) => PageAction::Copy,
+    };
+    let estimated_bytes = match action {
+        PageAction::Skip | PageAction::Fail => 0,
+        PageAction::Copy => std::fs::metadata(current_source)
+            .context(messages::metadata_failed(current_source))?
+            .len(),
+        PageAction::RemoveWatermark => estimate_encoded_bytes(width, height, &options.format),
+    };
+
+    Ok(PagePlan {
+        img_path: img_path.to_path_buf(),
+        source_path,
+        target_path,
+        out_image_path,
+        width,
+        height,
+        action,
+        conflict_resolution,
+        estimated_bytes,
+        error: None,
+    })
+}
+
+/// 统计规划的结果
+fn summarize(
+    options: &RemoveWatermarkOptions,
+    backgrounds: &Backgrounds,
+    chapters: Vec<ChapterPlan>,
+) -> RemoveWatermarkPlan {
+    let mut plan = RemoveWatermarkPlan {
+        manga_dir: options.manga_dir.clone(),
+        output_dir: options.output_dir.clone(),
+        chapters: vec![],
+        missing_backgrounds: vec![],
+        remove_count: 0,
+        copy_count: 0,
+        skip_count: 0,
+        fail_count: 0,
+        overwrite_count: 0,
+        estimated_bytes: 0,
+    };
+    // (width, height) => count
+    let mut missing_backgrounds: HashMap<(u32, u32), u32> = HashMap::new();
+    for page in chapters.iter().flat_map(|chapter| chapter.pages.iter()) {
+        match page.action {
+            PageAction::RemoveWatermark => plan.remove_count += 1,
+            PageAction::Copy => plan.copy_count += 1,
+            PageAction::Skip => plan.skip_count += 1,
+            PageAction::Fail => {
+                plan.fail_count += 1;
+                continue;
+            }
+        }
+        // 跳过的图片也统计在内，方便在处理之前补齐缺少的背景水印图
+        if backgrounds.get(page.width, page.height).is_none() {
+            *missing_backgrounds
+                .entry((page.width, page.height))
+                .or_insert(0) += 1;
+        }
+        if page.conflict_resolution == ConflictResolution::Overwritten {
+            plan.overwrite_count += 1;
+        }
+        plan.estimated_bytes += page.estimated_bytes;
+    }
+    plan.missing_backgrounds = missing_backgrounds
+        .into_iter()
+        .map(|((width, height), count)| SizeCount {
+            width,
+            height,
+            count,
+        })
+        .collect();
+    // 以count降序排序，count相同时按尺寸排序，保证顺序是确定的
+    plan.missing_backgrounds.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| (a.width, a.height).cmp(&(b.width, b.height)))
+    });
+    plan.chapters = chapters;
+    plan
+}
+
+/// 粗略估算去水印后的图片编码后的字节数
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+#[allow(clippy::cast_precision_loss)]
+fn estimate_encoded_bytes(width: u32, height: u32, format: &ImageFormat) -> u64 {
+    let bytes_per_pixel = match format {
+        ImageFormat::Jpeg => JPEG_BYTES_PER_PIXEL,
+        ImageFormat::Png => PNG_BYTES_PER_PIXEL,
+    };
+    let pixels = f64::from(width) * f64::from(height);
+    (pixels * bytes_per_pixel) as u64
+}
+
+/// 根据`policy`处理输出路径`out_image_path`已存在文件的情况，返回(实际的输出路径, 实际的处理方式)
+fn resolve_conflict(
+    out_image_path: PathBuf,
+    img_path: &Path,
+    policy: ConflictPolicy,
+    used_out_paths: &Mutex<HashSet<PathBuf>>,
+) -> anyhow::Result<(PathBuf, ConflictResolution)> {
+    if !out_image_path.exists() {
+        return Ok((out_image_path, ConflictResolution::NoConflict));
+    }
+    match policy {
+        ConflictPolicy::Overwrite => Ok((out_image_path, ConflictResolution::Overwritten)),
+        ConflictPolicy::Skip => Ok((out_image_path, ConflictResolution::Skipped)),
+        ConflictPolicy::SkipIfNewer => {
+            let modified = |path: &Path| -> anyhow::Result<SystemTime> {
+                let modified = std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .context(messages::modified_time_failed(path))?;
+                Ok(modified)
+            };
+            let resolution = if modified(&out_image_path)? > modified(img_path)? {
+                ConflictResolution::Skipped
+            } else {
+                ConflictResolution::Overwritten
+            };
+            Ok((out_image_path, resolution))
+        }
+        ConflictPolicy::Rename => {
+            let stem = out_image_path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .ok_or(anyhow!(messages::file_name_failed(&out_image_path)))?;
+            // 没有扩展名时不加`.`，否则会得到`stem (1).`
+            let ext = out_image_path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| format!(".{ext}"))
+                .unwrap_or_default();
+            // 找到第一个既不存在、也没有被其他图片使用的文件名
+            let renamed_path = (1..)
+                .map(|i| out_image_path.with_file_name(format!("{stem} ({i}){ext}")))
+                .find(|path| !path.exists() && used_out_paths.lock().insert(path.clone()))
+                .ok_or(anyhow!(messages::new_file_name_failed(&out_image_path)))?;
+            Ok((renamed_path, ConflictResolution::Renamed))
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::events::{RemoveWatermarkProgressEventPayload, RemoveWatermarkSummaryEventPayload};
+use crate::remover::pipeline::WrittenPage;
+use crate::types::{PageAction, RemoveWatermarkPlan};
+
+/// 发送`RemoveWatermarkProgressEvent`的间隔
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// 计算当前速度时，最新一段时间的速度所占的权重，越大速度变化越快，越小ETA越平稳
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// 统计一次去水印的整体进度，用于计算速度和剩余时间
+pub struct ProgressTracker {
+    manga_dir: PathBuf,
+    started: Instant,
+    // 所有图片的数量，包括跳过的图片
+    total: u32,
+    // 需要读取和写入的图片数量，不包括跳过的图片
+    work_total: u32,
+    done: AtomicU32,
+    work_done: AtomicU32,
+    removed: AtomicU32,
+    copied: AtomicU32,
+    skipped: AtomicU32,
+    failed: AtomicU32,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    rate: Mutex<RateSample>,
+}
+
+/// 上一次计算速度时的数据
+struct RateSample {
+    at: Instant,
+    work_done: u32,
+    bytes_read: u64,
+    bytes_written: u64,
+    // 平滑后的速度，(图片/秒, 读取字节/秒, 写入字节/秒)，还没有计算过时为`None`
+    smoothed: Option<(f64, f64, f64)>,
+}
+
+impl ProgressTracker {
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn new(manga_dir: PathBuf, plan: &RemoveWatermarkPlan) -> Self {
+        let pages = plan.chapters.iter().flat_map(|chapter| &chapter.pages);
+        let total = pages.clone().count() as u32;
+        let work_total = pages.filter(|page| page.action != PageAction::Skip).count() as u32;
+        let started = Instant::now();
+        Self {
+            manga_dir,
+            started,
+            total,
+            work_total,
+            done: AtomicU32::new(0),
+            work_done: AtomicU32::new(0),
+            removed: AtomicU32::new(0),
+            copied: AtomicU32::new(0),
+            skipped: AtomicU32::new(0),
+            failed: AtomicU32::new(0),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            rate: Mutex::new(RateSample {
+                at: started,
+                work_done: 0,
+                bytes_read: 0,
+                bytes_written: 0,
+                smoothed: None,
+            }),
+        }
+    }
+
+    /// 记录一张写入完成(或跳过)的图片
+    pub fn record(&self, written: &WrittenPage) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+        let counter = match written.page.action {
+            _ if written.error.is_some() => &self.failed,
+            PageAction::Skip => &self.skipped,
+            _ if written.watermark_removed => &self.removed,
+            _ => &self.copied,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if written.page.action != PageAction::Skip {
+            self.work_done.fetch_add(1, Ordering::Relaxed);
+        }
+        self.bytes_read
+            .fetch_add(written.read_bytes, Ordering::Relaxed);
+        self.bytes_written
+            .fetch_add(written.written_bytes, Ordering::Relaxed);
+    }
+
+    /// 当前的整体进度，速度是最近几次调用之间的平滑速度，跳过的图片不计入速度
+    #[allow(clippy::cast_precision_loss)]
+    pub fn progress(&self) -> RemoveWatermarkProgressEventPayload {
+        let work_done = self.work_done.load(Ordering::Relaxed);
+        let bytes_read = self.bytes_read.load(Ordering::Relaxed);
+        let bytes_written = self.bytes_written.load(Ordering::Relaxed);
+
+        let mut rate = self.rate.lock();
+        let now = Instant::now();
+        let interval_secs = now.duration_since(rate.at).as_secs_f64();
+        if interval_secs > 0.0 {
+            let latest = (
+                f64::from(work_done.saturating_sub(rate.work_done)) / interval_secs,
+                bytes_read.saturating_sub(rate.bytes_read) as f64 / interval_secs,
+                bytes_written.saturating_sub(rate.bytes_written) as f64 / interval_secs,
+            );
+            rate.smoothed = Some(match rate.smoothed {
+                Some(smoothed) => (
+                    smooth(smoothed.0, latest.0),
+                    smooth(smoothed.1, latest.1),
+                    smooth(smoothed.2, latest.2),
+                ),
+                None => latest,
+            });
+            rate.at = now;
+            rate.work_done = work_done;
+            rate.bytes_read = bytes_read;
+            rate.bytes_written = bytes_written;
+        }
+        let (pages_per_sec, read_bytes_per_sec, written_bytes_per_sec) =
+            rate.smoothed.unwrap_or_default();
+        // 速度为0时无法估计剩余时间
+        let remaining = self.work_total.saturating_sub(work_done);
+        let eta_secs = match remaining {
+            0 => Some(0.0),
+            _ if pages_per_sec > 0.0 => Some(f64::from(remaining) / pages_per_sec),
+            _ => None,
+        };
+
+        RemoveWatermarkProgressEventPayload {
+            manga_dir: self.manga_dir.clone(),
+            done: self.done.load(Ordering::Relaxed),
+            total: self.total,
+            bytes_read,
+            bytes_written,
+            pages_per_sec,
+            read_bytes_per_sec,
+            written_bytes_per_sec,
+            elapsed_secs: self.started.elapsed().as_secs_f64(),
+            eta_secs,
+        }
+    }
+
+    pub fn summary(&self) -> RemoveWatermarkSummaryEventPayload {
+        RemoveWatermarkSummaryEventPayload {
+            manga_dir: self.manga_dir.clone(),
+            total: self.total,
+            removed: self.removed.load(Ordering::Relaxed),
+            copied: self.copied.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            elapsed_secs: self.started.elapsed().as_secs_f64(),
+        }
+    }
+}
+
+fn smooth(smoothed: f64, latest: f64) -> f64 {
+    smoothed * (1.0 - RATE_SMOOTHING) + latest * RATE_SMOOTHING
+}
@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::Context;
+use parking_lot::{Condvar, Mutex};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::messages;
+
+/// 处理一个像素时内存的峰值字节数：解码得到的图片(最多4字节，RGBA)加上转换后的RGB图片(3字节)
+const DECODED_BYTES_PER_PIXEL: u64 = 7;
+
+/// 去水印使用的线程池和内存预算，所有任务共享，这样同时执行多个任务时也不会超出限制
+#[derive(Default)]
+pub struct ProcessingResources {
+    // (线程数, 线程池)，线程数改变时重新创建
+    pool: Mutex<Option<(usize, Arc<ThreadPool>)>>,
+    budget: MemoryBudget,
+    // 正在去水印的漫画目录，队列、监视和手动去水印共用
+    in_flight: Mutex<HashSet<PathBuf>>,
+}
+
+impl ProcessingResources {
+    /// 获取有`threads`个线程的线程池，`threads`为0时使用CPU的逻辑核心数
+    pub fn pool(&self, threads: usize) -> anyhow::Result<Arc<ThreadPool>> {
+        let threads = resolve_threads(threads);
+        let mut pool = self.pool.lock();
+        if let Some((pool_threads, pool)) = pool.as_ref() {
+            if *pool_threads == threads {
+                return Ok(pool.clone());
+            }
+        }
+        // 旧的线程池在所有使用它的任务结束后才会被drop
+        let new_pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("remover-{i}"))
+            .build()
+            .context(messages::create_thread_pool_failed(threads))?;
+        let new_pool = Arc::new(new_pool);
+        *pool = Some((threads, new_pool.clone()));
+        Ok(new_pool)
+    }
+
+    pub fn budget(&self) -> &MemoryBudget {
+        &self.budget
+    }
+
+    /// 标记漫画目录`manga_dir`正在去水印，已经在去水印时返回`None`
+    ///
+    /// 同一部漫画同时去水印会互相覆盖输出的图片、清单和备份，所以每次去水印前都要先调用，返回值drop后取消标记
+    pub fn claim(&self, manga_dir: &Path) -> Option<MangaClaim<'_>> {
+        if !self.in_flight.lock().insert(manga_dir.to_path_buf()) {
+            return None;
+        }
+        Some(MangaClaim {
+            resources: self,
+            manga_dir: manga_dir.to_path_buf(),
+        })
+    }
+}
+
+/// drop时取消漫画目录正在去水印的标记
+pub struct MangaClaim<'a> {
+    resources: &'a ProcessingResources,
+    manga_dir: PathBuf,
+}
+
+impl Drop for MangaClaim<'_> {
+    fn drop(&mut self) {
+        self.resources.in_flight.lock().remove(&self.manga_dir);
+    }
+}
+
+/// `threads`为0时返回CPU的逻辑核心数，否则原样返回
+pub fn resolve_threads(threads: usize) -> usize {
+    if threads == 0 {
+        std::thread::available_parallelism().map_or(1, usize::from)
+    } else {
+        threads
+    }
+}
+
+/// 限制同时解码的图片占用的内存
+#[derive(Default)]
+pub struct MemoryBudget {
+    // 已经被占用的字节数
+    in_use: Mutex<u64>,
+    released: Condvar,
+}
+
+impl MemoryBudget {
+    /// 为一张`width`x`height`的图片申请内存，超出`limit`字节时阻塞直到其他图片释放内存，`limit`为0时不限制
+    ///
+    /// 单张图片就超出`limit`时，等到没有其他图片占用内存后再放行，保证总能继续处理；
+    /// 等待期间`cancelled`变为true时返回`None`，设置`cancelled`后需要调用`wake_all`
+    pub fn acquire(
+        &self,
+        width: u32,
+        height: u32,
+        limit: u64,
+        cancelled: &AtomicBool,
+    ) -> Option<MemoryPermit<'_>> {
+        let bytes = u64::from(width) * u64::from(height) * DECODED_BYTES_PER_PIXEL;
+        let mut in_use = self.in_use.lock();
+        while limit != 0 && *in_use != 0 && *in_use + bytes > limit {
+            if cancelled.load(Ordering::Relaxed) {
+                return None;
+            }
+            self.released.wait(&mut in_use);
+        }
+        *in_use += bytes;
+        Some(MemoryPermit {
+            budget: self,
+            bytes,
+        })
+    }
+
+    /// 唤醒所有在`acquire`中等待的线程，让它们重新检查是否被取消
+    pub fn wake_all(&self) {
+        // 持有锁再唤醒，避免等待的线程检查完`cancelled`但还没开始等待时错过唤醒
+        let _in_use = self.in_use.lock();
+        self.released.notify_all();
+    }
+}
+
+/// drop时释放申请的内存
+pub struct MemoryPermit<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for MemoryPermit<'_> {
+    fn drop(&mut self) {
+        *self.budget.in_use.lock() -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use parking_lot::Mutex;
+
+use crate::errors::{ErrorKind, ResultKindContext};
+use crate::messages;
+use crate::types::{JobReport, PageOutcome, ReportFormat};
+
+/// 最多保留多少次去水印的报告
+const MAX_REPORTS: usize = 20;
+
+/// 最近几次去水印的报告，只保存在内存中，需要长期保留时用`save`保存到去水印后的漫画所在的目录
+#[derive(Default)]
+pub struct JobHistory {
+    // 按时间顺序排列，最新的在最后
+    reports: Mutex<VecDeque<JobReport>>,
+}
+
+impl JobHistory {
+    pub fn push(&self, report: JobReport) {
+        let mut reports = self.reports.lock();
+        if reports.len() == MAX_REPORTS {
+            reports.pop_front();
+        }
+        reports.push_back(report);
+    }
+
+    pub fn get(&self, id: &str) -> anyhow::Result<JobReport> {
+        self.reports
+            .lock()
+            .iter()
+            .find(|report| report.id == id)
+            .cloned()
+            .ok_or(anyhow!(messages::report_not_found(id)))
+    }
+}
+
+/// 将报告`report`以`format`格式保存到去水印后的漫画所在的目录中，返回保存的路径
+pub fn save(report: &JobReport, format: ReportFormat) -> anyhow::Result<PathBuf> {
+    let manga_name = report
+        .manga_dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(anyhow!(messages::manga_name_failed(&report.manga_dir)))?;
+    // 报告的开始时间是RFC 3339格式，其中的`:`不能用在Windows的文件名中
+    let timestamp: String = report
+        .started_at
+        .chars()
+        .take(19)
+        .filter(char::is_ascii_digit)
+        .collect();
+    let (content, extension) = match format {
+        ReportFormat::Json => (serde_json::to_string_pretty(report)?, "json"),
+        ReportFormat::Csv => (to_csv(report), "csv"),
+    };
+    // 保存到去水印后的漫画所在的目录，旧版本的报告中没有`output_root`，只能保存到输出目录
+    let report_dir = if report.output_root.as_os_str().is_empty() {
+        &report.output_dir
+    } else {
+        &report.output_root
+    };
+    let report_path = report_dir.join(messages::report_file_name(
+        manga_name, &timestamp, extension,
+    ));
+    std::fs::create_dir_all(report_dir).context(messages::create_dir_failed(report_dir))?;
+    std::fs::write(&report_path, content).kind_context(
+        ErrorKind::io(&report_path),
+        messages::save_report_failed(&report_path),
+    )?;
+    Ok(report_path)
+}
+
+/// 读取`save`保存的JSON格式的报告，CSV格式的报告没有记录完整的设置，无法读取
+pub fn load(report_path: &Path) -> anyhow::Result<JobReport> {
+    let content = std::fs::read_to_string(report_path).kind_context(
+        ErrorKind::io(report_path),
+        messages::read_report_failed(report_path),
+    )?;
+    let report =
+        serde_json::from_str(&content).context(messages::parse_report_failed(report_path))?;
+    Ok(report)
+}
+
+/// 每张图片一行，设置对所有图片都相同，也写在每一行中，方便用表格软件筛选
+fn to_csv(report: &JobReport) -> String {
+    // 以BOM开头，Excel才能正确识别UTF-8编码的中文路径
+    let mut csv = String::from('\u{feff}');
+    csv.push_str(
+        "img_path,out_image_path,outcome,width,height,background_version,output_bytes,duration_ms,format,optimize,error\n",
+    );
+    for page in &report.pages {
+        let outcome = match page.outcome {
+            PageOutcome::Removed => "removed",
+            PageOutcome::Copied => "copied",
+            PageOutcome::Skipped => "skipped",
+            PageOutcome::Failed => "failed",
+        };
+        let _ = writeln!(
+            csv,
+            "{},{},{outcome},{},{},{},{},{},{:?},{},{}",
+            csv_field(&path_string(&page.img_path)),
+            csv_field(&path_string(&page.out_image_path)),
+            page.width,
+            page.height,
+            page.background_version.as_deref().unwrap_or_default(),
+            page.output_bytes,
+            page.duration_ms,
+            report.format,
+            report.optimize,
+            csv_field(page.error.as_deref().unwrap_or_default()),
+        );
+    }
+    csv
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+/// 包含`,`、`"`或换行的字段需要用`"`包围，其中的`"`要写两次
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, Context};
+use parking_lot::RwLock;
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::messages;
+use crate::paths;
+use crate::types::{ImageInfo, SourceFormat};
+use crate::utils;
+
+/// 缓存的文件信息，只有文件的大小和修改时间都没变时才有效
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+    // 文件内容不是支持的图片格式时为None，扫描时不用再次读取这些文件
+    image: Option<CachedImage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedImage {
+    width: u32,
+    height: u32,
+    format: SourceFormat,
+}
+
+/// 持久化的图片尺寸缓存，避免每次扫描都要重新读取所有图片的尺寸
+pub struct ScanCache {
+    path: PathBuf,
+    entries: RwLock<HashMap<PathBuf, CacheEntry>>,
+    // 是否有未保存的修改
+    dirty: AtomicBool,
+}
+
+impl ScanCache {
+    pub fn load(app: &AppHandle) -> anyhow::Result<Self> {
+        let cache_dir = paths::cache_dir(app)?;
+        let path = cache_dir.join("scan_cache.json");
+        // 缓存文件损坏时直接丢弃，下次扫描会重新生成
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|cache_string| serde_json::from_str(&cache_string).ok())
+            .unwrap_or_default();
+        Ok(Self {
+            path,
+            entries: RwLock::new(entries),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// 获取图片`path`的信息，缓存失效时会重新读取并更新缓存
+    pub fn probe(&self, path: &Path) -> anyhow::Result<ImageInfo> {
+        let metadata = std::fs::metadata(path).context(messages::metadata_failed(path))?;
+        let modified = metadata
+            .modified()
+            .context(messages::modified_time_failed(path))?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let (size, modified_secs, modified_nanos) =
+            (metadata.len(), modified.as_secs(), modified.subsec_nanos());
+
+        if let Some(entry) = self.entries.read().get(path) {
+            if entry.size == size
+                && entry.modified_secs == modified_secs
+                && entry.modified_nanos == modified_nanos
+            {
+                let image = entry
+                    .image
+                    .as_ref()
+                    .ok_or(anyhow!(messages::unsupported_image_format(path)))?;
+                return Ok(ImageInfo {
+                    width: image.width,
+                    height: image.height,
+                    format: image.format,
+                    path: path.to_path_buf(),
+                });
+            }
+        }
+
+        // 根据文件内容而不是后缀判断图片格式
+        let format = SourceFormat::sniff(path)?;
+        // 读取尺寸失败可能只是暂时的(比如图片还没写完)，所以只缓存格式不支持的结果
+        let image = match format {
+            Some(format) => {
+                let (width, height) = utils::image_dimensions(path)?;
+                Some(CachedImage {
+                    width,
+                    height,
+                    format,
+                })
+            }
+            None => None,
+        };
+        let entry = CacheEntry {
+            size,
+            modified_secs,
+            modified_nanos,
+            image: image.clone(),
+        };
+        self.entries.write().insert(path.to_path_buf(), entry);
+        self.dirty.store(true, Ordering::Relaxed);
+
+        let CachedImage {
+            width,
+            height,
+            format,
+        } = image.ok_or(anyhow!(messages::unsupported_image_format(path)))?;
+
+        Ok(ImageInfo {
+            width,
+            height,
+            format,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// 判断`path`是否为支持的图片，扫描漫画目录时对每个文件调用
+    ///
+    /// 后缀是支持的图片格式时直接相信后缀，真实格式在获取图片信息时再判断；
+    /// 没有后缀或后缀不认识时才需要读取文件开头的magic bytes，缓存有效时不读取，
+    /// 判断的结果(包括不是图片的结果)也会存入缓存
+    pub fn is_image(&self, path: &Path) -> bool {
+        SourceFormat::from_path(path).is_some() || self.probe(path).is_ok()
+    }
+
+    /// 使用rayon并发获取`paths`中所有图片的信息，获取失败的图片会被忽略，返回结果的顺序与`paths`一致
+    pub fn probe_all(&self, paths: &[&PathBuf]) -> Vec<ImageInfo> {
+        paths
+            .par_iter()
+            .filter_map(|path| self.probe(path).ok())
+            .collect()
+    }
+
+    /// 如果有未保存的修改，则将缓存写入磁盘，写入前删除已经不存在的文件的缓存
+    pub fn save(&self) -> anyhow::Result<()> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.prune();
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(messages::create_dir_failed(parent))?;
+        }
+        let cache_string = serde_json::to_string(&*self.entries.read())?;
+        std::fs::write(&self.path, cache_string)
+            .context(messages::save_scan_cache_failed(&self.path))?;
+        Ok(())
+    }
+
+    /// 删除已经不存在的文件的缓存，否则删除或移动过的漫画的缓存会一直保留在缓存文件中
+    fn prune(&self) {
+        let missing_paths: Vec<PathBuf> = self
+            .entries
+            .read()
+            .par_iter()
+            .filter(|(path, _)| !path.is_file())
+            .map(|(path, _)| path.clone())
+            .collect();
+        let mut entries = self.entries.write();
+        for path in &missing_paths {
+            entries.remove(path);
+        }
+    }
+
+    /// 清空缓存，并删除缓存文件
+    pub fn clear(&self) -> anyhow::Result<()> {
+        self.entries.write().clear();
+        self.dirty.store(false, Ordering::Relaxed);
+        if self.path.exists() {
+            std::fs::remove_file(&self.path)
+                .context(messages::delete_scan_cache_failed(&self.path))?;
+        }
+        Ok(())
+    }
+}
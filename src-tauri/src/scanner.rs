@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::errors::{ErrorKind, ResultKindContext};
+use crate::messages;
+use crate::natural_sort;
+use crate::scan_cache::ScanCache;
+use crate::types::{ChapterTree, MangaTree, ScanOptions, SymlinkPolicy};
+
+/// 按照`options`扫描漫画目录`manga_dir`，构建漫画 → 章节 → 图片的树，章节和图片都按自然顺序排序
+///
+/// 所有需要遍历漫画目录的地方都应该使用这个函数，以保证统计的结果和实际处理的图片一致
+pub fn scan_manga_dir(
+    manga_dir: &Path,
+    options: &ScanOptions,
+    scan_cache: &ScanCache,
+) -> anyhow::Result<MangaTree> {
+    let mut chapters: Vec<ChapterTree> = vec![];
+    // chapter_dir => chapters中的索引
+    let mut chapter_indexes: HashMap<PathBuf, usize> = HashMap::new();
+    walk_images(manga_dir, options, scan_cache, |path| {
+        let Some(parent) = path.parent().map(Path::to_path_buf) else {
+            return;
+        };
+        let index = *chapter_indexes.entry(parent.clone()).or_insert_with(|| {
+            chapters.push(ChapterTree {
+                dir: parent,
+                pages: vec![],
+            });
+            chapters.len() - 1
+        });
+        chapters[index].pages.push(path);
+    })?;
+    // walkdir返回的顺序取决于文件系统，所以按自然顺序排序，保证章节和图片的顺序是确定的
+    chapters.sort_by(|a, b| natural_sort::compare_paths(&a.dir, &b.dir));
+    for chapter in &mut chapters {
+        chapter
+            .pages
+            .sort_by(|a, b| natural_sort::compare_paths(a, b));
+    }
+
+    tracing::info!(
+        manga_dir = ?manga_dir,
+        chapters = chapters.len(),
+        pages = chapters.iter().map(|chapter| chapter.pages.len()).sum::<usize>(),
+        "{}", messages::log_scan_finished()
+    );
+    Ok(MangaTree {
+        manga_dir: manga_dir.to_path_buf(),
+        chapters,
+    })
+}
+
+/// 按照`options`遍历漫画目录`manga_dir`，每找到一张图片就调用`on_image`，与`scan_manga_dir`使用相同的规则，
+/// 图片的顺序取决于文件系统，需要边遍历边处理时使用这个函数
+pub fn walk_images(
+    manga_dir: &Path,
+    options: &ScanOptions,
+    scan_cache: &ScanCache,
+    mut on_image: impl FnMut(PathBuf),
+) -> anyhow::Result<()> {
+    let include_set = build_glob_set(&options.include_patterns)?;
+    let exclude_set = build_glob_set(&options.exclude_patterns)?;
+
+    let mut walker =
+        WalkDir::new(manga_dir).follow_links(options.symlink_policy == SymlinkPolicy::Follow);
+    if let Some(max_depth) = options.max_depth {
+        walker = walker.max_depth(max_depth as usize);
+    }
+
+    walker
+        .into_iter()
+        .filter_entry(|entry| should_visit(entry, manga_dir, options, &exclude_set))
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            if options.symlink_policy == SymlinkPolicy::Skip && entry.path_is_symlink() {
+                return None;
+            }
+            let path = entry.into_path();
+            if !include_set.is_empty() && !include_set.is_match(relative_path(&path, manga_dir)) {
+                return None;
+            }
+            // 最后才判断是否为图片，可能需要读取文件内容
+            if !path.is_file() || !scan_cache.is_image(&path) {
+                return None;
+            }
+            Some(path)
+        })
+        .for_each(&mut on_image);
+    Ok(())
+}
+
+/// 判断是否需要访问`entry`，返回false时如果`entry`是目录，则整个目录都会被跳过
+fn should_visit(
+    entry: &DirEntry,
+    manga_dir: &Path,
+    options: &ScanOptions,
+    exclude_set: &GlobSet,
+) -> bool {
+    // 漫画目录本身总是需要访问
+    if entry.depth() == 0 {
+        return true;
+    }
+    let is_hidden = entry
+        .file_name()
+        .to_str()
+        .is_some_and(|name| name.starts_with('.'));
+    if is_hidden && !options.include_hidden {
+        return false;
+    }
+    !exclude_set.is_match(relative_path(entry.path(), manga_dir))
+}
+
+/// 获取`path`相对于`manga_dir`的路径，用于glob匹配
+fn relative_path<'a>(path: &'a Path, manga_dir: &Path) -> &'a Path {
+    path.strip_prefix(manga_dir).unwrap_or(path)
+}
+
+fn build_glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob =
+            Glob::new(pattern).kind_context(ErrorKind::Config, messages::invalid_glob(pattern))?;
+        builder.add(glob);
+    }
+    let glob_set = builder
+        .build()
+        .kind_context(ErrorKind::Config, messages::build_glob_set_failed())?;
+    Ok(glob_set)
+}
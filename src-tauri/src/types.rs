@@ -1,8 +1,12 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
+use crate::errors::{ErrorKind, ResultKindContext};
+use crate::messages;
+
 #[derive(Debug, Deserialize, Serialize, Type)]
 pub struct RectData {
     pub left: u32,
@@ -22,13 +26,21 @@ pub struct MangaDirData {
     pub white_background: Option<ImageData>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Type)]
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
 pub struct ImageInfo {
     pub width: u32,
     pub height: u32,
+    pub format: SourceFormat,
     pub path: PathBuf,
 }
 
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Type)]
+pub struct SizeCount {
+    pub width: u32,
+    pub height: u32,
+    pub count: u32,
+}
+
 #[derive(Debug, Deserialize, Serialize, Type)]
 pub struct ImageData {
     pub info: ImageInfo,
@@ -46,3 +58,516 @@ pub enum ImageFormat {
     Jpeg,
     Png,
 }
+
+#[cfg(feature = "jxl")]
+const JXL_CODESTREAM_SIGNATURE: &[u8] = &[0xFF, 0x0A];
+#[cfg(feature = "jxl")]
+const JXL_CONTAINER_SIGNATURE: &[u8] = &[
+    0x00, 0x00, 0x00, 0x0C, b'J', b'X', b'L', b' ', 0x0D, 0x0A, 0x87, 0x0A,
+];
+
+// 漫画目录中图片文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, Type)]
+pub enum SourceFormat {
+    Jpeg,
+    Png,
+    Webp,
+    Gif,
+    Bmp,
+    Tiff,
+    // 需要启用`avif` feature才能解码
+    Avif,
+    // 需要启用`jxl` feature才能解码
+    Jxl,
+}
+
+impl SourceFormat {
+    /// 根据`path`的后缀判断图片格式，后缀可能与图片的真实格式不一致
+    pub fn from_path(path: &Path) -> Option<Self> {
+        // image库不认识JPEG XL的后缀，没有启用`jxl` feature时当作不支持的格式
+        #[cfg(feature = "jxl")]
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("jxl"))
+        {
+            return Some(Self::Jxl);
+        }
+        Self::from_image_format(image::ImageFormat::from_path(path).ok()?)
+    }
+
+    /// 读取文件`path`开头的magic bytes来判断图片的真实格式，不是支持的图片格式时返回`None`
+    pub fn sniff(path: &Path) -> anyhow::Result<Option<Self>> {
+        use std::io::Read;
+        let mut header = Vec::with_capacity(32);
+        std::fs::File::open(path)
+            .kind_context(ErrorKind::io(path), messages::open_file_failed(path))?
+            .take(32)
+            .read_to_end(&mut header)
+            .kind_context(ErrorKind::io(path), messages::read_file_failed(path))?;
+        Ok(Self::sniff_bytes(&header))
+    }
+
+    /// 根据已经读入内存的数据`data`开头的magic bytes判断图片的真实格式
+    pub fn sniff_bytes(header: &[u8]) -> Option<Self> {
+        // image库不认识JPEG XL，需要手动判断(裸codestream或ISOBMFF容器)
+        #[cfg(feature = "jxl")]
+        if header.starts_with(JXL_CODESTREAM_SIGNATURE)
+            || header.starts_with(JXL_CONTAINER_SIGNATURE)
+        {
+            return Some(Self::Jxl);
+        }
+        image::guess_format(header)
+            .ok()
+            .and_then(Self::from_image_format)
+    }
+
+    fn from_image_format(format: image::ImageFormat) -> Option<Self> {
+        match format {
+            image::ImageFormat::Jpeg => Some(Self::Jpeg),
+            image::ImageFormat::Png => Some(Self::Png),
+            image::ImageFormat::WebP => Some(Self::Webp),
+            image::ImageFormat::Gif => Some(Self::Gif),
+            image::ImageFormat::Bmp => Some(Self::Bmp),
+            image::ImageFormat::Tiff => Some(Self::Tiff),
+            // 没有启用`avif` feature时无法解码AVIF，当作不支持的格式
+            #[cfg(feature = "avif")]
+            image::ImageFormat::Avif => Some(Self::Avif),
+            _ => None,
+        }
+    }
+
+    /// 浏览器(webview和EPUB阅读器)是否都能直接显示该格式的图片
+    pub fn is_web_compatible(self) -> bool {
+        matches!(self, Self::Jpeg | Self::Png | Self::Webp | Self::Gif)
+    }
+
+    /// 该格式的标准后缀
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Webp => "webp",
+            Self::Gif => "gif",
+            Self::Bmp => "bmp",
+            Self::Tiff => "tif",
+            Self::Avif => "avif",
+            Self::Jxl => "jxl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum ExportFormat {
+    Epub,
+    Pdf,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum ExportScope {
+    // 每个章节导出为一个文件
+    Chapter,
+    // 整部漫画导出为一个文件
+    #[default]
+    Manga,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum OutputLayout {
+    // 输出目录/漫画名/章节名/原文件名，与漫画目录的结构一致
+    #[default]
+    Mirrored,
+    // 输出目录/漫画名/章节序号-页序号.后缀，所有图片都放在同一个目录
+    Flattened,
+    // 使用Config中的output_template
+    Template,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum ConflictPolicy {
+    // 覆盖已存在的文件
+    #[default]
+    Overwrite,
+    // 已存在时跳过
+    Skip,
+    // 已存在且比原图片新时跳过，否则覆盖
+    SkipIfNewer,
+    // 已存在时在文件名后加上` (1)`、` (2)`等后缀
+    Rename,
+}
+
+// 输出路径已存在文件时实际的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum ConflictResolution {
+    // 输出路径不存在文件，没有冲突
+    NoConflict,
+    Overwritten,
+    Skipped,
+    Renamed,
+    // 原图片和设置都没有变化，增量处理时跳过
+    Unchanged,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum BackupMode {
+    // 原图片移动到漫画目录旁边的`漫画名.原图备份`目录
+    #[default]
+    Folder,
+    // 原图片打包到漫画目录旁边的`漫画名.原图备份.zip`
+    Archive,
+}
+
+// 加入队列或添加监视时从Config中复制的输出设置，之后修改配置不会影响已经加入的任务
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputSettings {
+    pub layout: OutputLayout,
+    pub template: String,
+    pub incremental: bool,
+    pub in_place: bool,
+    pub backup_mode: BackupMode,
+    pub export_format: Option<ExportFormat>,
+    pub export_scope: ExportScope,
+}
+
+// 后端返回的错误信息和事件中的文本使用的语言
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum Locale {
+    #[default]
+    ZhCn,
+    En,
+}
+
+impl Locale {
+    /// BCP 47语言标签，用于导出的EPUB的`dc:language`
+    pub fn language_tag(self) -> &'static str {
+        match self {
+            Locale::ZhCn => "zh-CN",
+            Locale::En => "en",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum SymlinkPolicy {
+    // 忽略所有符号链接
+    Skip,
+    // 只跟随指向文件的符号链接，不进入指向目录的符号链接
+    #[default]
+    FilesOnly,
+    // 跟随所有符号链接
+    Follow,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanOptions {
+    // 最大遍历深度，为`None`时不限制深度
+    pub max_depth: Option<u32>,
+    // 相对于漫画目录的glob模式，不为空时只扫描匹配其中任意一个模式的图片
+    pub include_patterns: Vec<String>,
+    // 相对于漫画目录的glob模式，匹配其中任意一个模式的文件和目录都会被跳过
+    pub exclude_patterns: Vec<String>,
+    // 是否扫描以`.`开头的隐藏文件和目录
+    pub include_hidden: bool,
+    pub symlink_policy: SymlinkPolicy,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: Some(2), // 一般第一层目录是章节目录，第二层目录是图片文件
+            include_patterns: vec![],
+            exclude_patterns: vec![],
+            include_hidden: false,
+            symlink_policy: SymlinkPolicy::FilesOnly,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterTree {
+    pub dir: PathBuf,
+    pub pages: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct MangaTree {
+    pub manga_dir: PathBuf,
+    pub chapters: Vec<ChapterTree>,
+}
+
+impl MangaTree {
+    pub fn pages(&self) -> impl Iterator<Item = &PathBuf> {
+        self.chapters
+            .iter()
+            .flat_map(|chapter| chapter.pages.iter())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum PageAction {
+    // 有对应尺寸的背景水印图，去除水印
+    RemoveWatermark,
+    // 没有对应尺寸的背景水印图，直接复制
+    Copy,
+    // 因为冲突策略或增量处理而跳过
+    Skip,
+    // 开启了出错后继续时，规划时就无法读取尺寸的图片(比如空文件)，执行时直接报告为失败
+    Fail,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PagePlan {
+    pub img_path: PathBuf,
+    // 实际读取的原图片路径，原地处理时是备份中的原图片
+    pub source_path: PathBuf,
+    // 根据输出目录结构得到的输出路径
+    pub target_path: PathBuf,
+    // 处理冲突后实际写入的输出路径
+    pub out_image_path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub action: PageAction,
+    pub conflict_resolution: ConflictResolution,
+    // 预计输出的字节数，跳过时为0
+    pub estimated_bytes: u64,
+    // 规划时的错误，只有`Fail`的图片才有
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterPlan {
+    pub dir: PathBuf,
+    pub name: String,
+    pub pages: Vec<PagePlan>,
+    // 章节中图片的总数，只处理部分图片时也包括没有处理的图片
+    pub page_count: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveWatermarkPlan {
+    pub manga_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub chapters: Vec<ChapterPlan>,
+    // 没有背景水印图的尺寸及其图片数量，这些图片会被直接复制
+    pub missing_backgrounds: Vec<SizeCount>,
+    pub remove_count: u32,
+    pub copy_count: u32,
+    pub skip_count: u32,
+    pub fail_count: u32,
+    // 会覆盖已存在文件的图片数量
+    pub overwrite_count: u32,
+    // 预计输出的总字节数
+    pub estimated_bytes: u64,
+}
+
+// 开启了出错后继续时，处理失败的图片
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PageFailure {
+    pub dir_path: PathBuf,
+    pub img_path: PathBuf,
+    pub err_msg: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueJob {
+    pub id: String,
+    pub manga_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub format: ImageFormat,
+    pub optimize: bool,
+    // [(黑色背景水印图路径, 白色背景水印图路径), ...]，执行时才读取，避免队列文件过大
+    pub backgrounds: Vec<(PathBuf, PathBuf)>,
+    pub comic_info: bool,
+    pub conflict_policy: ConflictPolicy,
+    // 加入队列时的输出设置，旧版本的队列文件中没有这个字段，此时使用当前的配置
+    #[serde(default)]
+    pub output_settings: Option<OutputSettings>,
+    pub status: JobStatus,
+    // 失败时的错误信息
+    pub error: Option<String>,
+    // 开启了出错后继续时，处理失败的图片
+    #[serde(default)]
+    pub failures: Vec<PageFailure>,
+    // 完成后报告的id，用于导出报告，报告只保存在内存中，重启后失效
+    #[serde(default)]
+    pub report_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueState {
+    pub jobs: Vec<QueueJob>,
+    // 队列是否正在执行，暂停后正在执行的任务会继续执行完
+    pub running: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchTarget {
+    pub manga_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub format: ImageFormat,
+    pub optimize: bool,
+    // [(黑色背景水印图路径, 白色背景水印图路径), ...]
+    pub backgrounds: Vec<(PathBuf, PathBuf)>,
+    pub comic_info: bool,
+    pub conflict_policy: ConflictPolicy,
+    // 添加监视时的输出设置，旧版本的监视文件中没有这个字段，此时使用当前的配置
+    #[serde(default)]
+    pub output_settings: Option<OutputSettings>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchState {
+    pub targets: Vec<WatchTarget>,
+    // 是否正在监视，重启后会恢复
+    pub enabled: bool,
+}
+
+// 去水印流水线中每个阶段的线程数，为0时自动决定
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineThreads {
+    // 读取原图片，为0时使用4个线程
+    pub reader: u32,
+    // 解码图片，为0时使用`worker_threads`
+    pub decoder: u32,
+    // 去除水印，为0时使用`worker_threads`
+    pub kernel: u32,
+    // 编码图片，为0时使用`worker_threads`
+    pub encoder: u32,
+    // 写入输出图片，为0时使用2个线程
+    pub writer: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum PipelineStage {
+    Reader,
+    Decoder,
+    Kernel,
+    Encoder,
+    Writer,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct StageStats {
+    pub stage: PipelineStage,
+    pub threads: u32,
+    // 这个阶段实际处理的图片数，直接经过的图片(例如复制的图片经过解码阶段)不算
+    pub items: u32,
+    // 读取和写入阶段是文件的字节数，解码和去水印阶段是像素数据的字节数，编码阶段是编码后的字节数
+    pub bytes: u64,
+    // 所有线程处理图片花费的时间之和，不包括等待上下游的时间
+    pub busy_secs: f64,
+    pub items_per_sec: f64,
+    pub mb_per_sec: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum PageOutcome {
+    // 去除了水印
+    Removed,
+    // 没有对应尺寸的背景水印图，原样复制
+    Copied,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct PageReport {
+    pub dir_path: PathBuf,
+    pub img_path: PathBuf,
+    pub out_image_path: PathBuf,
+    pub outcome: PageOutcome,
+    pub width: u32,
+    pub height: u32,
+    // 去水印时使用的背景水印图的版本
+    pub background_version: Option<String>,
+    // 写入的输出图片字节数，跳过和失败时为0
+    pub output_bytes: u64,
+    // 从开始读取到写入完成花费的毫秒数，包括在流水线阶段之间等待的时间
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportBackground {
+    pub width: u32,
+    pub height: u32,
+    pub version: String,
+}
+
+// 一次去水印的报告，记录每张图片的处理结果和使用的设置
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct JobReport {
+    pub id: String,
+    pub manga_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub format: ImageFormat,
+    pub optimize: bool,
+    pub comic_info: bool,
+    pub conflict_policy: ConflictPolicy,
+    pub layout: OutputLayout,
+    pub template: String,
+    pub in_place: bool,
+    pub incremental: bool,
+    pub backgrounds: Vec<ReportBackground>,
+    // RFC 3339格式的本地时间
+    pub started_at: String,
+    pub finished_at: String,
+    pub elapsed_secs: f64,
+    pub removed: u32,
+    pub copied: u32,
+    pub skipped: u32,
+    pub failed: u32,
+    pub pages: Vec<PageReport>,
+    // 去水印后的漫画实际所在的目录，原地处理时就是漫画目录，旧版本的报告中没有这个字段
+    #[serde(default)]
+    pub output_root: PathBuf,
+    // 去水印后导出的文件
+    #[serde(default)]
+    pub exported: Vec<PathBuf>,
+    // 导出失败时的错误信息，导出失败不影响去水印的结果
+    #[serde(default)]
+    pub export_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Type)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Type)]
+#[serde(rename_all = "camelCase")]
+pub struct LogEntry {
+    // RFC 3339格式的本地时间
+    pub timestamp: String,
+    pub level: String,
+    // 产生日志的模块
+    pub target: String,
+    pub message: String,
+    // 日志附带的其他字段，例如漫画目录、图片数量
+    pub fields: BTreeMap<String, String>,
+}
@@ -1,19 +1,37 @@
+#[cfg(feature = "jxl")]
+use std::fs::File;
+#[cfg(feature = "jxl")]
+use std::io::BufReader;
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
 
-use tauri::{AppHandle, Manager};
+use anyhow::{anyhow, Context};
+#[cfg(feature = "jxl")]
+use image::ImageDecoder;
+use image::{DynamicImage, ImageReader};
+#[cfg(feature = "jxl")]
+use jxl_oxide::integration::JxlDecoder;
+use tauri::AppHandle;
+
+use crate::errors::{ErrorKind, ResultKindContext};
+use crate::messages;
+use crate::paths;
+use crate::types::SourceFormat;
 
 pub fn get_background_dir_relative_path(
     manga_dir: &str,
     width: u32,
     height: u32,
 ) -> anyhow::Result<PathBuf> {
-    let manga_dir_name = Path::new(manga_dir)
+    let manga_dir = Path::new(manga_dir);
+    let manga_dir_name = manga_dir
         .file_name()
-        .ok_or(anyhow::anyhow!("获取漫画目录名失败"))?
+        .ok_or(anyhow!(messages::manga_name_failed(manga_dir)))?
         .to_str()
-        .ok_or(anyhow::anyhow!("漫画目录名包含非UTF-8字符"))?;
-    let relative_path = format!("背景水印图/{manga_dir_name}{width}x{height}");
-    Ok(PathBuf::from(relative_path))
+        .ok_or(anyhow!(messages::manga_dir_non_utf8(manga_dir)))?;
+    let relative_path =
+        Path::new(paths::BACKGROUND_DIR_NAME).join(format!("{manga_dir_name}{width}x{height}"));
+    Ok(relative_path)
 }
 
 pub fn get_background_dir_abs_path(
@@ -22,8 +40,109 @@ pub fn get_background_dir_abs_path(
     width: u32,
     height: u32,
 ) -> anyhow::Result<PathBuf> {
-    let resource_dir = app.path().resource_dir()?;
+    let data_dir = paths::data_dir(app)?;
     let relative_path = get_background_dir_relative_path(manga_dir, width, height)?;
-    let abs_path = resource_dir.join(relative_path);
+    let abs_path = data_dir.join(relative_path);
     Ok(abs_path)
 }
+
+/// 转义`text`中的XML特殊字符
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// 根据图片的真实格式(而不是后缀)解码图片`path`
+pub fn decode_image(path: &Path) -> anyhow::Result<DynamicImage> {
+    #[cfg(feature = "jxl")]
+    if SourceFormat::sniff(path)? == Some(SourceFormat::Jxl) {
+        let decoder = open_jxl_decoder(path)?;
+        let img = DynamicImage::from_decoder(decoder)
+            .kind_context(ErrorKind::decode(path), messages::decode_image_failed(path))?;
+        return Ok(img);
+    }
+    let img = ImageReader::open(path)
+        .kind_context(ErrorKind::io(path), messages::open_image_failed(path))?
+        .with_guessed_format()?
+        .decode()
+        .kind_context(ErrorKind::decode(path), messages::decode_image_failed(path))?;
+    Ok(img)
+}
+
+/// 根据图片的真实格式解码已经读入内存的图片数据`data`，`path`是数据来源，只用于错误信息
+pub fn decode_image_bytes(data: &[u8], path: &Path) -> anyhow::Result<DynamicImage> {
+    #[cfg(feature = "jxl")]
+    if SourceFormat::sniff_bytes(data) == Some(SourceFormat::Jxl) {
+        let decoder = JxlDecoder::new(Cursor::new(data)).kind_context(
+            ErrorKind::decode(path),
+            messages::read_jxl_header_failed(path),
+        )?;
+        let img = DynamicImage::from_decoder(decoder)
+            .kind_context(ErrorKind::decode(path), messages::decode_image_failed(path))?;
+        return Ok(img);
+    }
+    let img = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()?
+        .decode()
+        .kind_context(ErrorKind::decode(path), messages::decode_image_failed(path))?;
+    Ok(img)
+}
+
+/// 根据图片的真实格式(而不是后缀)获取图片`path`的尺寸
+pub fn image_dimensions(path: &Path) -> anyhow::Result<(u32, u32)> {
+    #[cfg(feature = "jxl")]
+    if SourceFormat::sniff(path)? == Some(SourceFormat::Jxl) {
+        let decoder = open_jxl_decoder(path)?;
+        return Ok(decoder.dimensions());
+    }
+    let dimensions = ImageReader::open(path)
+        .kind_context(ErrorKind::io(path), messages::open_image_failed(path))?
+        .with_guessed_format()?
+        .into_dimensions()
+        .kind_context(
+            ErrorKind::decode(path),
+            messages::image_dimensions_failed(path),
+        )?;
+    Ok(dimensions)
+}
+
+/// 读取图片`path`的数据，浏览器不一定能显示的格式会被转换为png，返回(数据的格式, 数据)
+pub fn read_web_compatible_image(path: &Path) -> anyhow::Result<(SourceFormat, Vec<u8>)> {
+    let format =
+        SourceFormat::sniff(path)?.ok_or(anyhow!(messages::unsupported_image_format(path)))?;
+    if format.is_web_compatible() {
+        let data = std::fs::read(path)
+            .kind_context(ErrorKind::io(path), messages::read_image_failed(path))?;
+        return Ok((format, data));
+    }
+
+    let mut data = vec![];
+    decode_image(path)?
+        .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+        .context(messages::convert_to_png_failed(path))?;
+    Ok((SourceFormat::Png, data))
+}
+
+/// image库不支持JPEG XL，所以使用jxl-oxide提供的`ImageDecoder`实现
+#[cfg(feature = "jxl")]
+fn open_jxl_decoder(path: &Path) -> anyhow::Result<JxlDecoder<BufReader<File>>> {
+    let file =
+        File::open(path).kind_context(ErrorKind::io(path), messages::open_image_failed(path))?;
+    let decoder = JxlDecoder::new(BufReader::new(file)).kind_context(
+        ErrorKind::decode(path),
+        messages::read_jxl_header_failed(path),
+    )?;
+    Ok(decoder)
+}
+
+/// 如果`path`的后缀与图片的真实格式`format`不一致，则将后缀改为`format`的标准后缀
+pub fn with_format_extension(path: &Path, format: SourceFormat) -> PathBuf {
+    if SourceFormat::from_path(path) == Some(format) {
+        path.to_path_buf()
+    } else {
+        path.with_extension(format.extension())
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::{Mutex, RwLock};
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+
+use crate::config::Config;
+use crate::events;
+use crate::extensions::{AnyhowErrorToStringChain, PathIsImage};
+use crate::messages;
+use crate::paths;
+use crate::remover::{self, Backgrounds, ProcessingResources, RemoveWatermarkOptions};
+use crate::scan_cache::ScanCache;
+use crate::types::{WatchState, WatchTarget};
+
+/// 检查章节目录是否已经稳定的间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 监视漫画目录，有新图片时自动去水印，监视的目录在重启后依然保留
+pub struct MangaWatcher {
+    path: PathBuf,
+    state: RwLock<WatchState>,
+    // 正在监视时不为`None`，drop后监视停止，处理线程也会随之退出
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+/// 一个章节目录中等待处理的新图片
+struct PendingChapter {
+    manga_dir: PathBuf,
+    img_paths: HashSet<PathBuf>,
+    last_event: Instant,
+}
+
+impl MangaWatcher {
+    pub fn load(app: &AppHandle) -> anyhow::Result<Self> {
+        let data_dir = paths::data_dir(app)?;
+        let path = data_dir.join("watch.json");
+        // 文件损坏时直接丢弃
+        let state = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|watch_string| serde_json::from_str(&watch_string).ok())
+            .unwrap_or_default();
+        Ok(Self {
+            path,
+            state: RwLock::new(state),
+            watcher: Mutex::new(None),
+        })
+    }
+
+    pub fn state(&self) -> WatchState {
+        self.state.read().clone()
+    }
+
+    /// 添加要监视的漫画目录，已经在监视的目录会更新设置
+    pub fn add_target(&self, target: WatchTarget) -> anyhow::Result<()> {
+        if !target.manga_dir.is_dir() {
+            return Err(anyhow!(messages::manga_dir_not_found(&target.manga_dir)));
+        }
+        if let Some(watcher) = self.watcher.lock().as_mut() {
+            watcher
+                .watch(&target.manga_dir, RecursiveMode::Recursive)
+                .context(messages::watch_dir_failed(&target.manga_dir))?;
+        }
+        {
+            let mut state = self.state.write();
+            state
+                .targets
+                .retain(|watched| watched.manga_dir != target.manga_dir);
+            state.targets.push(target);
+        }
+        self.save()
+    }
+
+    pub fn remove_target(&self, manga_dir: &Path) -> anyhow::Result<()> {
+        if let Some(watcher) = self.watcher.lock().as_mut() {
+            // 目录可能已经被删除，这时取消监视会失败，但没有影响
+            let _ = watcher.unwatch(manga_dir);
+        }
+        self.state
+            .write()
+            .targets
+            .retain(|target| target.manga_dir != manga_dir);
+        self.save()
+    }
+
+    /// 开始或停止监视
+    pub fn set_enabled(&self, app: &AppHandle, enabled: bool) -> anyhow::Result<()> {
+        if enabled {
+            self.start(app)?;
+        } else {
+            // drop watcher后事件通道关闭，处理线程处理完手上的章节后退出
+            *self.watcher.lock() = None;
+        }
+        self.state.write().enabled = enabled;
+        self.save()
+    }
+
+    /// 开始监视所有目录，已经在监视时什么都不做
+    pub fn start(&self, app: &AppHandle) -> anyhow::Result<()> {
+        let mut watcher_guard = self.watcher.lock();
+        if watcher_guard.is_some() {
+            return Ok(());
+        }
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // 接收端已经退出时说明监视已经停止，忽略剩下的事件
+            let _ = sender.send(event);
+        })
+        .context(messages::create_watcher_failed())?;
+        for target in &self.state.read().targets {
+            watcher
+                .watch(&target.manga_dir, RecursiveMode::Recursive)
+                .context(messages::watch_dir_failed(&target.manga_dir))?;
+        }
+        *watcher_guard = Some(watcher);
+
+        let app = app.clone();
+        std::thread::spawn(move || run_debouncer(&app, &receiver));
+        Ok(())
+    }
+
+    fn target(&self, manga_dir: &Path) -> Option<WatchTarget> {
+        self.state
+            .read()
+            .targets
+            .iter()
+            .find(|target| target.manga_dir == manga_dir)
+            .cloned()
+    }
+
+    /// 返回`path`所在的被监视的漫画目录
+    fn manga_dir_of(&self, path: &Path) -> Option<PathBuf> {
+        self.state
+            .read()
+            .targets
+            .iter()
+            .find(|target| path.starts_with(&target.manga_dir))
+            .map(|target| target.manga_dir.clone())
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(messages::create_dir_failed(parent))?;
+        }
+        let watch_string = serde_json::to_string_pretty(&*self.state.read())?;
+        std::fs::write(&self.path, watch_string)
+            .context(messages::save_watch_state_failed(&self.path))?;
+        Ok(())
+    }
+}
+
+/// 收集文件变化事件，章节目录在`watch_settle_secs`秒内没有新的变化后，处理其中的新图片
+fn run_debouncer(app: &AppHandle, receiver: &Receiver<notify::Result<notify::Event>>) {
+    let watcher = app.state::<MangaWatcher>();
+    let config = app.state::<RwLock<Config>>();
+    let resources = app.state::<ProcessingResources>();
+    // 章节目录 => 等待处理的新图片
+    let mut pending: HashMap<PathBuf, PendingChapter> = HashMap::new();
+    // 去水印时写入的图片 => 写入完成的时间，原地处理时这些图片也会触发事件，需要忽略
+    let mut written: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        let settle = Duration::from_secs(config.read().watch_settle_secs);
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) if is_new_file_event(event.kind) => {
+                for path in event.paths {
+                    let just_written = written
+                        .get(&path)
+                        .is_some_and(|written_at| written_at.elapsed() < settle);
+                    if just_written {
+                        continue;
+                    }
+                    let (Some(manga_dir), Some(chapter_dir)) =
+                        (watcher.manga_dir_of(&path), path.parent())
+                    else {
+                        continue;
+                    };
+                    let chapter = pending.entry(chapter_dir.to_path_buf()).or_insert_with(|| {
+                        PendingChapter {
+                            manga_dir,
+                            img_paths: HashSet::new(),
+                            last_event: Instant::now(),
+                        }
+                    });
+                    chapter.img_paths.insert(path);
+                    chapter.last_event = Instant::now();
+                }
+            }
+            // 其他事件和监视器内部的错误都忽略
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        written.retain(|_, written_at| written_at.elapsed() < settle);
+        let settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, chapter)| chapter.last_event.elapsed() >= settle)
+            .map(|(chapter_dir, _)| chapter_dir.clone())
+            .collect();
+        for chapter_dir in settled {
+            let Some(chapter) = pending.remove(&chapter_dir) else {
+                continue;
+            };
+            // 监视的目录可能已经被移除
+            let Some(target) = watcher.target(&chapter.manga_dir) else {
+                continue;
+            };
+            // 这部漫画正在队列中或被手动去水印时，放回去等它完成后再处理
+            let Some(_claim) = resources.claim(&target.manga_dir) else {
+                pending.insert(chapter_dir, chapter);
+                continue;
+            };
+            match process_new_images(app, &target, chapter.img_paths) {
+                Ok(out_image_paths) => {
+                    let now = Instant::now();
+                    written.extend(out_image_paths.into_iter().map(|path| (path, now)));
+                }
+                Err(err) => {
+                    let payload = events::WatchErrorEventPayload {
+                        manga_dir: target.manga_dir.clone(),
+                        err_msg: err.to_string_chain(),
+                    };
+                    let _ = events::WatchErrorEvent(payload).emit(app);
+                }
+            }
+        }
+    }
+}
+
+/// 是否是可能产生新图片的事件(创建、写入、重命名)
+fn is_new_file_event(kind: EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_))
+    )
+}
+
+/// 使用监视目录保存的设置和背景水印图处理新图片，返回写入的图片路径
+fn process_new_images(
+    app: &AppHandle,
+    target: &WatchTarget,
+    img_paths: HashSet<PathBuf>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    // 重命名或删除后不存在的文件，以及还没写完而无法识别的文件都会被跳过
+    let img_paths: HashSet<PathBuf> = img_paths
+        .into_iter()
+        .filter(|path| path.is_file() && path.is_image())
+        .collect();
+    if img_paths.is_empty() {
+        return Ok(vec![]);
+    }
+    let config = app.state::<RwLock<Config>>();
+    let scan_cache = app.state::<ScanCache>();
+    // 输出结构、原地处理等设置使用添加监视时的设置
+    let config = match &target.output_settings {
+        Some(output_settings) => config.read().with_output_settings(output_settings),
+        None => config.read().clone(),
+    };
+    let mut options = RemoveWatermarkOptions::new(
+        &config,
+        &target.manga_dir,
+        &target.output_dir,
+        target.format.clone(),
+        target.optimize,
+        target.comic_info,
+        target.conflict_policy,
+    );
+    options.only_pages = Some(img_paths);
+    let backgrounds = Backgrounds::open(&target.backgrounds)?;
+    let outcome = remover::remove_watermark(app, &options, &backgrounds, &scan_cache)?;
+    let out_image_paths = outcome
+        .plan
+        .chapters
+        .into_iter()
+        .flat_map(|chapter| chapter.pages)
+        .map(|page| page.out_image_path)
+        .collect();
+    Ok(out_image_paths)
+}
@@ -13,9 +13,9 @@ async generateBackground(mangaDir: string, rectData: RectData | null, width: num
     else return { status: "error", error: e  as any };
 }
 },
-async removeWatermark(mangaDir: string, outputDir: string, format: ImageFormat, optimize: boolean, backgroundsData: ([ImageData, ImageData])[]) : Promise<Result<null, CommandError>> {
+async removeWatermark(mangaDir: string, outputDir: string, format: ImageFormat, optimize: boolean, backgroundsData: ([ImageData, ImageData])[], comicInfo: boolean, conflictPolicy: ConflictPolicy) : Promise<Result<JobReport, CommandError>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("remove_watermark", { mangaDir, outputDir, format, optimize, backgroundsData }) };
+    return { status: "ok", data: await TAURI_INVOKE("remove_watermark", { mangaDir, outputDir, format, optimize, backgroundsData, comicInfo, conflictPolicy }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -37,8 +37,13 @@ async getMangaDirData(mangaDir: string) : Promise<Result<MangaDirData[], Command
     else return { status: "error", error: e  as any };
 }
 },
-async getImageInfos(mangaDir: string) : Promise<ImageInfo[]> {
-    return await TAURI_INVOKE("get_image_infos", { mangaDir });
+async getImageInfos(mangaDir: string) : Promise<Result<ImageInfo[], CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_image_infos", { mangaDir }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 },
 async showPathInFileManager(path: string) : Promise<void> {
     await TAURI_INVOKE("show_path_in_file_manager", { path });
@@ -69,6 +74,172 @@ async saveConfig(config: Config) : Promise<Result<null, CommandError>> {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
+},
+async exportManga(mangaDir: string, exportDir: string, format: ExportFormat, scope: ExportScope) : Promise<Result<string[], CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_manga", { mangaDir, exportDir, format, scope }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async scanMangaDir(mangaDir: string) : Promise<Result<MangaTree, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("scan_manga_dir", { mangaDir }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async clearScanCache() : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clear_scan_cache") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async streamImageInfos(mangaDir: string) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("stream_image_infos", { mangaDir }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async restoreOriginals(mangaDir: string, chapterDir: string | null) : Promise<Result<string[], CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("restore_originals", { mangaDir, chapterDir }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async planRemoveWatermark(mangaDir: string, outputDir: string, format: ImageFormat, optimize: boolean, backgroundsData: ([ImageData, ImageData])[], comicInfo: boolean, conflictPolicy: ConflictPolicy) : Promise<Result<RemoveWatermarkPlan, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("plan_remove_watermark", { mangaDir, outputDir, format, optimize, backgroundsData, comicInfo, conflictPolicy }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async enqueueJob(mangaDir: string, outputDir: string, format: ImageFormat, optimize: boolean, backgrounds: ([string, string])[], comicInfo: boolean, conflictPolicy: ConflictPolicy) : Promise<Result<QueueJob, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("enqueue_job", { mangaDir, outputDir, format, optimize, backgrounds, comicInfo, conflictPolicy }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getQueue() : Promise<QueueState> {
+    return await TAURI_INVOKE("get_queue");
+},
+async moveJob(id: string, index: number) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("move_job", { id, index }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async removeJob(id: string) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_job", { id }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async startQueue() : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("start_queue") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async pauseQueue() : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("pause_queue") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async clearFinishedJobs() : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("clear_finished_jobs") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async retryJob(id: string) : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("retry_job", { id }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getWatchState() : Promise<WatchState> {
+    return await TAURI_INVOKE("get_watch_state");
+},
+async addWatchDir(mangaDir: string, outputDir: string, format: ImageFormat, optimize: boolean, backgrounds: ([string, string])[], comicInfo: boolean, conflictPolicy: ConflictPolicy) : Promise<Result<WatchState, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("add_watch_dir", { mangaDir, outputDir, format, optimize, backgrounds, comicInfo, conflictPolicy }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async removeWatchDir(mangaDir: string) : Promise<Result<WatchState, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("remove_watch_dir", { mangaDir }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setWatchEnabled(enabled: boolean) : Promise<Result<WatchState, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_watch_enabled", { enabled }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async saveJobReport(reportId: string, format: ReportFormat) : Promise<Result<string, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("save_job_report", { reportId, format }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async retryFailed(reportId: string | null, reportPath: string | null) : Promise<Result<JobReport, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("retry_failed", { reportId, reportPath }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async readLog(count: number) : Promise<Result<LogEntry[], CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("read_log", { count }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async openLogDir() : Promise<Result<null, CommandError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("open_log_dir") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 }
 }
 
@@ -76,15 +247,29 @@ async saveConfig(config: Config) : Promise<Result<null, CommandError>> {
 
 
 export const events = __makeEvents__<{
+pipelineStatsEvent: PipelineStatsEvent,
+queueUpdateEvent: QueueUpdateEvent,
 removeWatermarkEndEvent: RemoveWatermarkEndEvent,
 removeWatermarkErrorEvent: RemoveWatermarkErrorEvent,
+removeWatermarkProgressEvent: RemoveWatermarkProgressEvent,
 removeWatermarkStartEvent: RemoveWatermarkStartEvent,
-removeWatermarkSuccessEvent: RemoveWatermarkSuccessEvent
+removeWatermarkSuccessEvent: RemoveWatermarkSuccessEvent,
+removeWatermarkSummaryEvent: RemoveWatermarkSummaryEvent,
+scanBatchEvent: ScanBatchEvent,
+scanEndEvent: ScanEndEvent,
+watchErrorEvent: WatchErrorEvent
 }>({
+pipelineStatsEvent: "pipeline-stats-event",
+queueUpdateEvent: "queue-update-event",
 removeWatermarkEndEvent: "remove-watermark-end-event",
 removeWatermarkErrorEvent: "remove-watermark-error-event",
+removeWatermarkProgressEvent: "remove-watermark-progress-event",
 removeWatermarkStartEvent: "remove-watermark-start-event",
-removeWatermarkSuccessEvent: "remove-watermark-success-event"
+removeWatermarkSuccessEvent: "remove-watermark-success-event",
+removeWatermarkSummaryEvent: "remove-watermark-summary-event",
+scanBatchEvent: "scan-batch-event",
+scanEndEvent: "scan-end-event",
+watchErrorEvent: "watch-error-event"
 })
 
 /** user-defined constants **/
@@ -93,21 +278,67 @@ removeWatermarkSuccessEvent: "remove-watermark-success-event"
 
 /** user-defined types **/
 
-export type CommandError = string
-export type Config = { outputDir: string; outputFormat: ImageFormat; outputOptimize: boolean }
+export type BackupMode = "Folder" | "Archive"
+export type ChapterPlan = { dir: string; name: string; pages: PagePlan[]; pageCount: number }
+export type ChapterTree = { dir: string; pages: string[] }
+export type CommandError = { code: "IO"; path: string | null; chain: string } | { code: "DECODE"; path: string; chain: string } | { code: "ENCODE"; path: string; chain: string } | { code: "BACKGROUND_MISSING"; width: number; height: number; chain: string } | { code: "SIZE_MISMATCH"; width: number; height: number; expectedWidth: number; expectedHeight: number; chain: string } | { code: "CONFIG"; chain: string } | { code: "OTHER"; chain: string }
+export type Config = { outputDir: string; outputFormat: ImageFormat; outputOptimize: boolean; outputComicInfo?: boolean; outputConflictPolicy?: ConflictPolicy; outputIncremental?: boolean; outputLayout?: OutputLayout; outputTemplate?: string; inPlace?: boolean; backupMode?: BackupMode; exportFormat?: ExportFormat | null; exportScope?: ExportScope; scanOptions?: ScanOptions; queueConcurrency?: number; watchSettleSecs?: number; workerThreads?: number; memoryBudgetMb?: number; pipelineThreads?: PipelineThreads; continueOnError?: boolean; locale?: Locale }
+export type ConflictPolicy = "Overwrite" | "Skip" | "SkipIfNewer" | "Rename"
+export type ConflictResolution = "NoConflict" | "Overwritten" | "Skipped" | "Renamed" | "Unchanged"
+export type ExportFormat = "Epub" | "Pdf"
+export type ExportScope = "Chapter" | "Manga"
 export type ImageData = { info: ImageInfo; data: number[] }
 export type ImageFormat = "Jpeg" | "Png"
-export type ImageInfo = { width: number; height: number; path: string }
+export type ImageInfo = { width: number; height: number; format: SourceFormat; path: string }
+export type JobReport = { id: string; mangaDir: string; outputDir: string; format: ImageFormat; optimize: boolean; comicInfo: boolean; conflictPolicy: ConflictPolicy; layout: OutputLayout; template: string; inPlace: boolean; incremental: boolean; backgrounds: ReportBackground[]; startedAt: string; finishedAt: string; elapsedSecs: number; removed: number; copied: number; skipped: number; failed: number; pages: PageReport[]; outputRoot?: string; exported?: string[]; exportError?: string | null }
+export type JobStatus = "Pending" | "Running" | "Completed" | "Failed"
+export type Locale = "ZhCn" | "En"
+export type LogEntry = { timestamp: string; level: string; target: string; message: string; fields: { [key in string]: string } }
 export type MangaDirData = { width: number; height: number; count: number; blackBackground: ImageData | null; whiteBackground: ImageData | null }
+export type MangaTree = { mangaDir: string; chapters: ChapterTree[] }
+export type OutputLayout = "Mirrored" | "Flattened" | "Template"
+export type OutputSettings = { layout: OutputLayout; template: string; incremental: boolean; inPlace: boolean; backupMode: BackupMode; exportFormat: ExportFormat | null; exportScope: ExportScope }
+export type PageAction = "RemoveWatermark" | "Copy" | "Skip" | "Fail"
+export type PageFailure = { dirPath: string; imgPath: string; errMsg: string }
+export type PageOutcome = "Removed" | "Copied" | "Skipped" | "Failed"
+export type PagePlan = { imgPath: string; sourcePath: string; targetPath: string; outImagePath: string; width: number; height: number; action: PageAction; conflictResolution: ConflictResolution; estimatedBytes: number; error: string | null }
+export type PageReport = { dirPath: string; imgPath: string; outImagePath: string; outcome: PageOutcome; width: number; height: number; backgroundVersion: string | null; outputBytes: number; durationMs: number; error: string | null }
+export type PipelineStage = "Reader" | "Decoder" | "Kernel" | "Encoder" | "Writer"
+export type PipelineStatsEvent = PipelineStatsEventPayload
+export type PipelineStatsEventPayload = { mangaDir: string; stages: StageStats[] }
+export type PipelineThreads = { reader: number; decoder: number; kernel: number; encoder: number; writer: number }
+export type QueueJob = { id: string; mangaDir: string; outputDir: string; format: ImageFormat; optimize: boolean; backgrounds: ([string, string])[]; comicInfo: boolean; conflictPolicy: ConflictPolicy; outputSettings?: OutputSettings | null; status: JobStatus; error: string | null; failures?: PageFailure[]; reportId?: string | null }
+export type QueueState = { jobs: QueueJob[]; running: boolean }
+export type QueueUpdateEvent = QueueState
 export type RectData = { left: number; top: number; right: number; bottom: number }
 export type RemoveWatermarkEndEvent = RemoveWatermarkEndEventPayload
 export type RemoveWatermarkEndEventPayload = { dirPath: string }
 export type RemoveWatermarkErrorEvent = RemoveWatermarkErrorEventPayload
 export type RemoveWatermarkErrorEventPayload = { dirPath: string; imgPath: string; errMsg: string }
+export type RemoveWatermarkPlan = { mangaDir: string; outputDir: string; chapters: ChapterPlan[]; missingBackgrounds: SizeCount[]; removeCount: number; copyCount: number; skipCount: number; failCount: number; overwriteCount: number; estimatedBytes: number }
+export type RemoveWatermarkProgressEvent = RemoveWatermarkProgressEventPayload
+export type RemoveWatermarkProgressEventPayload = { mangaDir: string; done: number; total: number; bytesRead: number; bytesWritten: number; pagesPerSec: number; readBytesPerSec: number; writtenBytesPerSec: number; elapsedSecs: number; etaSecs: number | null }
 export type RemoveWatermarkStartEvent = RemoveWatermarkStartEventPayload
 export type RemoveWatermarkStartEventPayload = { dirPath: string; total: number }
 export type RemoveWatermarkSuccessEvent = RemoveWatermarkSuccessEventPayload
-export type RemoveWatermarkSuccessEventPayload = { dirPath: string; imgPath: string; current: number }
+export type RemoveWatermarkSuccessEventPayload = { dirPath: string; imgPath: string; current: number; conflictResolution: ConflictResolution }
+export type RemoveWatermarkSummaryEvent = RemoveWatermarkSummaryEventPayload
+export type RemoveWatermarkSummaryEventPayload = { mangaDir: string; total: number; removed: number; copied: number; skipped: number; failed: number; bytesRead: number; bytesWritten: number; elapsedSecs: number }
+export type ReportBackground = { width: number; height: number; version: string }
+export type ReportFormat = "Json" | "Csv"
+export type ScanBatchEvent = ScanBatchEventPayload
+export type ScanBatchEventPayload = { mangaDir: string; imageInfos: ImageInfo[]; sizeCounts: SizeCount[]; scanned: number; total: number }
+export type ScanEndEvent = ScanEndEventPayload
+export type ScanEndEventPayload = { mangaDir: string; total: number }
+export type ScanOptions = { maxDepth: number | null; includePatterns: string[]; excludePatterns: string[]; includeHidden: boolean; symlinkPolicy: SymlinkPolicy }
+export type SizeCount = { width: number; height: number; count: number }
+export type SourceFormat = "Jpeg" | "Png" | "Webp" | "Gif" | "Bmp" | "Tiff" | "Avif" | "Jxl"
+export type StageStats = { stage: PipelineStage; threads: number; items: number; bytes: number; busySecs: number; itemsPerSec: number; mbPerSec: number }
+export type SymlinkPolicy = "Skip" | "FilesOnly" | "Follow"
+export type WatchErrorEvent = WatchErrorEventPayload
+export type WatchErrorEventPayload = { mangaDir: string; errMsg: string }
+export type WatchState = { targets: WatchTarget[]; enabled: boolean }
+export type WatchTarget = { mangaDir: string; outputDir: string; format: ImageFormat; optimize: boolean; backgrounds: ([string, string])[]; comicInfo: boolean; conflictPolicy: ConflictPolicy; outputSettings?: OutputSettings | null }
 
 /** tauri-specta globals **/
 
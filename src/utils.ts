@@ -16,7 +16,7 @@ export async function getBackgroundDirRelativePath(
 ): Promise<string | null> {
   const result = await commands.getBackgroundDirRelativePath(mangaDir, width, height)
   if (result.status === 'error') {
-    notification.error({ title: '获取背景水印图相对路径失败', description: result.error })
+    notification.error({ title: '获取背景水印图相对路径失败', description: result.error.chain })
     return null
   }
   return result.data
@@ -30,7 +30,7 @@ export async function getBackgroundDirAbsPath(
 ): Promise<string | null> {
   const result = await commands.getBackgroundDirAbsPath(mangaDir, width, height)
   if (result.status === 'error') {
-    notification.error({ title: '获取背景水印图绝对路径失败', description: result.error })
+    notification.error({ title: '获取背景水印图绝对路径失败', description: result.error.chain })
     return null
   }
   return result.data
@@ -44,9 +44,11 @@ export async function autoGenerateBackground(
 ): Promise<boolean> {
   const result = await commands.generateBackground(mangaDir, null, width, height)
   if (result.status === 'error') {
-    notification.error({
+    // 找不到背景水印图是正常情况，需要用户手动截取水印区域
+    const notify = result.error.code === 'BACKGROUND_MISSING' ? notification.warning : notification.error
+    notify({
       title: `自动生成背景水印图(${width}x${height})失败`,
-      description: result.error,
+      description: result.error.chain,
     })
     return false
   }